@@ -3,7 +3,7 @@ extern crate test;
 use self::test::Bencher;
 use crate::Entity;
 
-use crate::cm::*;
+use crate::ComponentManager;
 
 #[allow(dead_code)]
 struct GroupedPos {
@@ -55,6 +55,7 @@ fn ecs_add_diffrent_groups(b: &mut Bencher) {
 		let entity = manager.add_entity();
 		manager.add_component(&entity, PosComp{x: 0, y: 0, z: 0,});
 		manager.add_component(&entity, VelComp{x: 0, y: 0, z: 0,});
+		manager.kill_entity(entity);
 	});
 }
 
@@ -66,6 +67,7 @@ fn ecs_add_same_group(b: &mut Bencher) {
 		let entity = manager.add_entity();
 		manager.add_component(&entity, PosComp{x: 0, y: 0, z: 0,});
 		manager.add_component(&entity, RenderComp{x: 0, y: 0, z: 0,});
+		manager.kill_entity(entity);
 	});
 }
 
@@ -88,6 +90,31 @@ fn ecs_kill_one(b: &mut Bencher) {
 	});
 }
 
+#[bench]
+fn ecs_spawn_loop(b: &mut Bencher) {
+	b.iter(|| {
+		let manager = Manager::new();
+		for i in 0..10_000 {
+			let entity = manager.add_entity();
+			manager.add_component(&entity, PosComp{x: i, y: 0, z: 0,});
+			manager.add_component(&entity, RenderComp{x: 0, y: 0, z: 0,});
+			manager.add_component(&entity, VelComp{x: 0, y: 0, z: 0,});
+		}
+		manager
+	});
+}
+
+#[bench]
+fn ecs_spawn_batch(b: &mut Bencher) {
+	b.iter(|| {
+		let manager = Manager::new();
+		manager.spawn_batch((0..10_000).map(|i| {
+			(PosComp{x: i, y: 0, z: 0,}, RenderComp{x: 0, y: 0, z: 0,}, VelComp{x: 0, y: 0, z: 0,})
+		}));
+		manager
+	});
+}
+
 /* 
 #[bench]
 fn cm_add_grouped_random(b: &mut Bencher) {
@@ -0,0 +1,246 @@
+use crate::{Component, ComponentManager, Entity, Manager};
+
+type GroupFn = fn(&ComponentManager, &Entity);
+
+///
+/// A set of components added to an entity in one operation. Implemented for tuples
+/// of up to eight components. The entity is grouped once after every component is added,
+/// instead of after each of them.
+///
+/// # Examples
+///
+/// ```
+/// use rust_ecs::*;
+/// struct Pos(i32);
+/// struct Vel(i32);
+/// struct Name(&'static str);
+///
+/// group!(Pos, Vel);
+/// register_components!(Name);
+///
+/// let manager = Manager::new();
+///
+/// let entity = manager.add_entity();
+/// manager.add_bundle(&entity, (Pos(0), Vel(1), Name("ship")));
+/// assert_eq!(1, manager.get_comp_manager().group_len::<Pos>());
+/// ```
+pub trait Bundle: Send + Sized + 'static {
+    /// Adds every component to the entity without grouping it.
+    fn insert(self, cm: &mut ComponentManager, entity: &Entity);
+
+    ///
+    /// Adds the bundles to the entities at the same positions without grouping them.
+    /// The components are split by type first, so every set is looked up and grown once.
+    ///
+    fn insert_batch(bundles: Vec<Self>, cm: &mut ComponentManager, entities: &[Entity]);
+
+    /// Groups the entity for every component of the bundle.
+    fn group(cm: &ComponentManager, entity: &Entity);
+
+    /// Groups the entities for every component of the bundle, see [`Component::group_batch`].
+    fn group_batch(cm: &ComponentManager, entities: &[Entity]);
+}
+
+impl Bundle for () {
+    fn insert(self, _: &mut ComponentManager, _: &Entity) {}
+
+    fn insert_batch(_: Vec<Self>, _: &mut ComponentManager, _: &[Entity]) {}
+
+    fn group(_: &ComponentManager, _: &Entity) {}
+
+    fn group_batch(_: &ComponentManager, _: &[Entity]) {}
+}
+
+macro_rules! impl_bundle {
+    ($(($comp:ident, $index:tt)),+) => {
+        impl<$($comp: Component),+> Bundle for ($($comp,)+) {
+            fn insert(self, cm: &mut ComponentManager, entity: &Entity) {
+                $(cm.insert_component(entity, self.$index);)+
+            }
+
+            fn insert_batch(bundles: Vec<Self>, cm: &mut ComponentManager, entities: &[Entity]) {
+                let mut columns = ($(Vec::<$comp>::with_capacity(bundles.len()),)+);
+                for bundle in bundles {
+                    $(columns.$index.push(bundle.$index);)+
+                }
+                $(cm.insert_components(entities, columns.$index);)+
+            }
+
+            fn group(cm: &ComponentManager, entity: &Entity) {
+                $($comp::group(cm, entity);)+
+            }
+
+            fn group_batch(cm: &ComponentManager, entities: &[Entity]) {
+                $($comp::group_batch(cm, entities);)+
+            }
+        }
+    };
+}
+
+impl_bundle!((A, 0));
+impl_bundle!((A, 0), (B, 1));
+impl_bundle!((A, 0), (B, 1), (C, 2));
+impl_bundle!((A, 0), (B, 1), (C, 2), (D, 3));
+impl_bundle!((A, 0), (B, 1), (C, 2), (D, 3), (E, 4));
+impl_bundle!((A, 0), (B, 1), (C, 2), (D, 3), (E, 4), (F, 5));
+impl_bundle!((A, 0), (B, 1), (C, 2), (D, 3), (E, 4), (F, 5), (G, 6));
+impl_bundle!((A, 0), (B, 1), (C, 2), (D, 3), (E, 4), (F, 5), (G, 6), (H, 7));
+
+///
+/// Builder adding components to a newly spawned entity, see [`Manager::spawn`].
+/// Every component is added right away, but the entity is grouped only once,
+/// when the builder is finished with [`EntityBuilder::id`] or dropped.
+///
+/// # Panics
+///
+/// Adding components panics if the comp_manager is borrowed currently.
+///
+/// # Examples
+///
+/// ```
+/// use rust_ecs::*;
+/// struct Pos(i32);
+/// struct Vel(i32);
+///
+/// group!(Pos, Vel);
+///
+/// let manager = Manager::new();
+///
+/// let entity = manager.spawn().with(Pos(0)).with(Vel(1)).id();
+/// assert!(manager.get_comp_manager().has_component::<Vel>(&entity));
+/// assert_eq!(1, manager.get_comp_manager().group_len::<Pos>());
+/// ```
+pub struct EntityBuilder<'m> {
+    manager: &'m Manager,
+    entity: Entity,
+    groups: Vec<GroupFn>,
+}
+
+impl<'m> EntityBuilder<'m> {
+    pub(crate) fn new(manager: &'m Manager, entity: Entity) -> Self {
+        EntityBuilder {
+            manager,
+            entity,
+            groups: Vec::new(),
+        }
+    }
+
+    /// Adds a component to the entity.
+    pub fn with<T: Component>(mut self, component: T) -> Self {
+        self.manager.get_comp_manager_mut().insert_component(&self.entity, component);
+        self.groups.push(T::group);
+        self
+    }
+
+    /// Adds every component of the bundle to the entity.
+    pub fn with_bundle<B: Bundle>(mut self, bundle: B) -> Self {
+        bundle.insert(&mut self.manager.get_comp_manager_mut(), &self.entity);
+        self.groups.push(B::group);
+        self
+    }
+
+    /// Finishes the entity and returns its identifier.
+    pub fn id(self) -> Entity {
+        self.entity
+    }
+}
+
+impl Drop for EntityBuilder<'_> {
+    fn drop(&mut self) {
+        if self.groups.is_empty() {
+            return;
+        }
+        // Dropped while unwinding from a failed borrow, the entity just stays ungrouped.
+        if let Ok(cm) = self.manager.try_get_comp_manager() {
+            for group in self.groups.iter() {
+                group(&cm, &self.entity);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Pos(i32);
+    struct Vel(i32);
+    struct Hp(u32);
+
+    struct Sprite;
+
+    group!(Pos, Vel);
+    register_components!(Hp);
+    group_partial!(Sprite => Pos);
+
+    #[test]
+    fn bundle_groups() {
+        let manager = Manager::new();
+        for i in 0..4 {
+            let entity = manager.add_entity();
+            if i % 2 == 0 {
+                manager.add_bundle(&entity, (Hp(i), Vel(i as i32), Pos(i as i32)));
+            } else {
+                manager.add_bundle(&entity, (Pos(i as i32), Hp(i)));
+            }
+        }
+
+        let cm = manager.get_comp_manager();
+        assert_eq!(cm.group_len::<Pos>(), 2);
+        assert_eq!(cm.group_len::<Vel>(), 2);
+        let mut query = cm.query::<(&Pos, &Vel)>();
+        assert!(query.is_grouped());
+        let entities: Vec<u32> = query.iter().map(|(e, pos, vel)| {
+            assert_eq!(pos.0, vel.0);
+            e.get_index()
+        }).collect();
+        assert_eq!(entities, vec![0, 2]);
+        assert_eq!(cm.query::<(&Hp,)>().iter().map(|(_, hp)| hp.0).sum::<u32>(), 6);
+    }
+
+    #[test]
+    fn builder() {
+        let manager = Manager::new();
+        let first = manager.spawn().with(Vel(1)).with_bundle((Hp(3),)).with(Pos(1)).id();
+        let second = manager.spawn().with(Pos(2)).id();
+        {
+            let builder = manager.spawn().with(Pos(3));
+            assert_eq!(manager.get_comp_manager().group_len::<Pos>(), 1);
+            builder.with(Vel(3));
+        }
+
+        assert!(manager.entity_alive(first) && manager.entity_alive(second));
+        let cm = manager.get_comp_manager();
+        assert_eq!(cm.group_len::<Pos>(), 2);
+        assert!(cm.has_component::<Hp>(&first));
+        assert!(!cm.has_component::<Vel>(&second));
+    }
+
+    #[test]
+    fn batch_groups() {
+        let manager = Manager::new();
+        manager.spawn_batch((0..3).map(|i| (Pos(i), Hp(0))));
+        let entities = manager.spawn_batch((3..6).map(|i| (Vel(i), Sprite, Pos(i))));
+        manager.spawn_batch((6..9).map(|i| (Sprite, Vel(i))));
+
+        let cm = manager.get_comp_manager();
+        assert_eq!(cm.group_len::<Pos>(), 3);
+        assert_eq!(cm.group_len::<Sprite>(), 3);
+        let mut query = cm.query::<(&Pos, &Vel)>();
+        assert!(query.is_grouped());
+        assert_eq!(query.iter().map(|(e, _, _)| e).collect::<Vec<_>>(), entities);
+        for (_, pos, vel) in query.iter() {
+            assert_eq!(pos.0, vel.0);
+        }
+        let sprites = cm.get_components::<Sprite>().unwrap();
+        assert_eq!((0..3).map(|i| *sprites.key_at(i).unwrap()).collect::<Vec<_>>(), entities);
+    }
+
+    #[test]
+    fn entity_with_macro() {
+        let manager = Manager::new();
+        let entity = crate::entity_with!(&manager, Pos(1), Vel(1), Hp(1));
+        assert_eq!(manager.query::<(&Pos, &Vel, &Hp)>().iter().map(|(e, _, _, _)| e).collect::<Vec<_>>(), vec![entity]);
+        assert_eq!(manager.get_comp_manager().group_len::<Vel>(), 1);
+    }
+}
@@ -0,0 +1,198 @@
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Flag value of a mutably borrowed cell. Any other value is the amount of shared borrows.
+const WRITING: usize = usize::MAX;
+
+///
+/// Error returned when a borrow conflicts with an existing borrow.
+/// Holds the type name of the borrowed value.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BorrowError {
+    /// The value is already borrowed mutably.
+    MutablyBorrowed(&'static str),
+    /// The value is already borrowed, so it can't be borrowed mutably.
+    Borrowed(&'static str),
+}
+
+impl std::fmt::Display for BorrowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BorrowError::MutablyBorrowed(name) => write!(f, "{} is already mutably borrowed", name),
+            BorrowError::Borrowed(name) => write!(f, "{} is already borrowed", name),
+        }
+    }
+}
+
+impl std::error::Error for BorrowError {}
+
+///
+/// A RefCell that can be shared between threads. Borrows are tracked with an
+/// atomic flag and never block, conflicting borrows fail instead.
+///
+pub(crate) struct AtomicRefCell<T> {
+    flag: AtomicUsize,
+    value: UnsafeCell<T>,
+}
+
+// Safety: the flag makes sure there is either one mutable borrow or any amount of shared borrows.
+unsafe impl<T: Send> Send for AtomicRefCell<T> {}
+unsafe impl<T: Send + Sync> Sync for AtomicRefCell<T> {}
+
+impl<T> AtomicRefCell<T> {
+    pub fn new(value: T) -> Self {
+        AtomicRefCell {
+            flag: AtomicUsize::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn try_borrow(&self) -> Result<AtomicRef<'_, T>, BorrowError> {
+        let mut current = self.flag.load(Ordering::Relaxed);
+        loop {
+            if current == WRITING {
+                return Err(BorrowError::MutablyBorrowed(std::any::type_name::<T>()));
+            }
+            match self.flag.compare_exchange_weak(current, current + 1, Ordering::Acquire, Ordering::Relaxed) {
+                Ok(_) => {
+                    return Ok(AtomicRef {
+                        // Safety: the flag was raised, so nobody can borrow mutably until the guard drops.
+                        value: unsafe { &*self.value.get() },
+                        flag: &self.flag,
+                    });
+                },
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    pub fn try_borrow_mut(&self) -> Result<AtomicRefMut<'_, T>, BorrowError> {
+        match self.flag.compare_exchange(0, WRITING, Ordering::Acquire, Ordering::Relaxed) {
+            Ok(_) => Ok(AtomicRefMut {
+                // Safety: the flag was set to WRITING, so nobody else can borrow until the guard drops.
+                value: unsafe { &mut *self.value.get() },
+                flag: &self.flag,
+            }),
+            Err(WRITING) => Err(BorrowError::MutablyBorrowed(std::any::type_name::<T>())),
+            Err(_) => Err(BorrowError::Borrowed(std::any::type_name::<T>())),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently mutably borrowed.
+    pub fn borrow(&self) -> AtomicRef<'_, T> {
+        self.try_borrow().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently borrowed.
+    pub fn borrow_mut(&self) -> AtomicRefMut<'_, T> {
+        self.try_borrow_mut().unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+///
+/// Shared borrow of a value in a cell. The borrow is released when dropped.
+///
+pub struct AtomicRef<'b, T> {
+    value: &'b T,
+    flag: &'b AtomicUsize,
+}
+
+impl<T> std::ops::Deref for AtomicRef<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.value
+    }
+}
+
+impl<T> Drop for AtomicRef<'_, T> {
+    fn drop(&mut self) {
+        self.flag.fetch_sub(1, Ordering::Release);
+    }
+}
+
+///
+/// Mutable borrow of a value in a cell. The borrow is released when dropped.
+///
+pub struct AtomicRefMut<'b, T> {
+    value: &'b mut T,
+    flag: &'b AtomicUsize,
+}
+
+impl<T> std::ops::Deref for AtomicRefMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.value
+    }
+}
+
+impl<T> std::ops::DerefMut for AtomicRefMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.value
+    }
+}
+
+impl<T> Drop for AtomicRefMut<'_, T> {
+    fn drop(&mut self) {
+        self.flag.store(0, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_borrows() {
+        let cell = AtomicRefCell::new(5);
+        let a = cell.borrow();
+        let b = cell.borrow();
+
+        assert_eq!(*a + *b, 10);
+        assert_eq!(cell.try_borrow_mut().err(), Some(BorrowError::Borrowed("i32")));
+    }
+
+    #[test]
+    fn mutable_borrow() {
+        let cell = AtomicRefCell::new(5);
+        {
+            let mut a = cell.borrow_mut();
+            *a = 6;
+            assert_eq!(cell.try_borrow().err(), Some(BorrowError::MutablyBorrowed("i32")));
+            assert_eq!(cell.try_borrow_mut().err(), Some(BorrowError::MutablyBorrowed("i32")));
+        }
+        assert_eq!(*cell.borrow(), 6);
+        assert!(cell.try_borrow_mut().is_ok());
+    }
+
+    #[test]
+    fn threads() {
+        let cell = AtomicRefCell::new(0);
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..1000 {
+                        if let Ok(mut value) = cell.try_borrow_mut() {
+                            *value += 1;
+                        }
+                        let _ = cell.try_borrow().map(|value| *value);
+                    }
+                });
+            }
+        });
+        assert!(*cell.borrow() > 0);
+        assert!(cell.try_borrow_mut().is_ok());
+    }
+}
@@ -1,19 +1,40 @@
+use std::sync::Mutex;
+
 use crate::sparse_set::*;
-use std::cell::RefCell;
-use crate::Entity;
+use crate::ecs::cell::AtomicRefCell;
+use crate::{Entity, Events};
+
+pub type HookFn<T> = Box<dyn Fn(Entity, &T) + Send + Sync>;
+pub type ReplaceHookFn<T> = Box<dyn Fn(Entity, &T, &T) + Send + Sync>;
+
+///
+/// Callbacks fired when components of a family are added, replaced or removed.
+///
+pub struct Hooks<T> {
+    pub on_add: Vec<HookFn<T>>,
+    pub on_replace: Vec<ReplaceHookFn<T>>,
+    pub on_remove: Vec<HookFn<T>>,
+}
 
 pub struct Family<T> {
-    pub components: RefCell<SparseSet<Entity, T>>,
+    pub components: AtomicRefCell<SparseSet<Entity, T>>,
+    pub hooks: Hooks<T>,
+    /// Entities that lost their component, logged once a reader is registered and kept like events.
+    pub removed: Mutex<Events<Entity>>,
 }
 
 impl<T> Family<T> {
     pub fn new() -> Self {
-        Family{ components: RefCell::new(SparseSet::new()), }
+        Family{
+            components: AtomicRefCell::new(SparseSet::new()),
+            hooks: Hooks { on_add: Vec::new(), on_replace: Vec::new(), on_remove: Vec::new() },
+            removed: Mutex::new(Events::default()),
+        }
     }
 }
 
 pub struct Container {
-    families: std::collections::HashMap<std::any::TypeId, Box<dyn std::any::Any>>,
+    families: std::collections::HashMap<std::any::TypeId, Box<dyn std::any::Any + Send + Sync>>,
 }
 
 impl Container {
@@ -23,7 +44,7 @@ impl Container {
         }
     }
 
-    pub fn add_family<T: std::any::Any>(&mut self, family: Family<T>) {
+    pub fn add_family<T: std::any::Any + Send + Sync>(&mut self, family: Family<T>) {
         self.families.insert(std::any::TypeId::of::<T>(), Box::new(family));
     }
 
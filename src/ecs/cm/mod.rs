@@ -1,18 +1,62 @@
 mod family_manager;
 
+use std::any::{Any, TypeId};
+use std::collections::hash_map::{Entry, HashMap};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{MutexGuard, RwLock};
+
 use crate::sparse_set;
 use crate::Entity;
 use crate::Component;
+use crate::query::{Query, QueryData, QueryFilter};
+use crate::ecs::cell::AtomicRefCell;
+use crate::ecs::relations::{OnTargetKilled, Relation, Relations};
+use crate::ecs::snapshot::{self, ComponentSnapshot};
+use crate::ecs::save::ComponentCodec;
+use crate::ecs::transfer;
+#[cfg(feature = "serde")]
+use crate::ecs::serialize::ComponentSerializer;
+use crate::{AtomicRef, AtomicRefMut, BorrowError, Bundle, Events};
+
+pub type View<'l, T> = AtomicRef<'l, sparse_set::SparseSet<Entity, T>>;
+pub type ViewMut<'l, T> = AtomicRefMut<'l, sparse_set::SparseSet<Entity, T>>;
+
+type CleanFn = Box<dyn Fn(&ComponentManager, Entity) + Send + Sync>;
+type UngroupFn = Box<dyn Fn(&ComponentManager, Entity, std::any::TypeId) + Send + Sync>;
+type UpdateFn = Box<dyn Fn(&ComponentManager) + Send + Sync>;
+type RelationCleanFn = Box<dyn Fn(&ComponentManager, Entity, &mut Vec<Entity>) + Send + Sync>;
+type SnapshotFn = fn(&ComponentManager) -> ComponentSnapshot;
+pub(crate) type GroupFn = fn(&ComponentManager, &Entity);
+type CloneFn = fn(&mut ComponentManager, &Entity, &Entity) -> Option<GroupFn>;
+type MoveFn = Box<dyn Fn(&ComponentManager, &mut ComponentManager, &Entity, &Entity) -> Option<GroupFn> + Send + Sync>;
 
-pub type View<'l, T> = std::cell::Ref<'l, sparse_set::SparseSet<Entity, T>>;
-pub type ViewMut<'l, T> = std::cell::RefMut<'l, sparse_set::SparseSet<Entity, T>>;
+/// Type erased functions of a component type registered with [`ComponentManager::register_cloneable`].
+struct Cloneable {
+    type_id: TypeId,
+    snapshot: SnapshotFn,
+    clone: CloneFn,
+}
 
 ///
 /// Sub manager to handle component part of the ecs.
 /// 
 pub struct ComponentManager {
     family_container: family_manager::Container,
-	cleans: Vec<Box<dyn Fn(&ComponentManager, Entity)>>,
+	cleans: Vec<CleanFn>,
+	ungroups: Vec<UngroupFn>,
+	removal_updates: Vec<UpdateFn>,
+	// Component types with a RemovedComponents reader, the only ones whose removals are logged.
+	removal_readers: RwLock<HashSet<TypeId>>,
+	clears: Vec<(TypeId, UpdateFn)>,
+	moves: Vec<MoveFn>,
+	cloneables: Vec<Cloneable>,
+	codecs: Vec<ComponentCodec>,
+	#[cfg(feature = "serde")]
+	serializers: Vec<ComponentSerializer>,
+	relations: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+	relation_cleans: Vec<RelationCleanFn>,
+	change_tick: AtomicU32,
 }
 
 impl ComponentManager {
@@ -20,15 +64,37 @@ impl ComponentManager {
         ComponentManager {
             family_container: family_manager::Container::new(),
 			cleans: Vec::new(),
+			ungroups: Vec::new(),
+			removal_updates: Vec::new(),
+			removal_readers: RwLock::new(HashSet::new()),
+			clears: Vec::new(),
+			moves: Vec::new(),
+			cloneables: Vec::new(),
+			codecs: Vec::new(),
+			#[cfg(feature = "serde")]
+			serializers: Vec::new(),
+			relations: HashMap::new(),
+			relation_cleans: Vec::new(),
+			change_tick: AtomicU32::new(1),
         }
     }
 
     ///
-    /// Adds a new component to an entity.
-    /// 
-    /// # Panics
-    /// 
-    /// Panics if any other thread adds or works with components currently.
+    /// The tick components added or mutably borrowed right now are stamped with.
+    /// Every run of a typed system advances it, see [`Added`](crate::Added) and [`Changed`](crate::Changed).
+    ///
+    pub fn change_tick(&self) -> u32 {
+        self.change_tick.load(Ordering::Acquire)
+    }
+
+    /// Advances the change tick, returning the tick before the increment.
+    pub(crate) fn increment_change_tick(&self) -> u32 {
+        self.change_tick.fetch_add(1, Ordering::AcqRel)
+    }
+
+    ///
+    /// Adds a new component to an entity, replacing the component of the same type if it has one.
+    /// Fires the on_add or on_replace hooks of the component type.
     ///
     /// # Examples
     ///
@@ -44,25 +110,381 @@ impl ComponentManager {
     /// manager.get_comp_manager_mut().add_component(&entity, Comp {});
     /// ```
     pub fn add_component<T: Component >(&mut self, entity: &Entity, component: T) {
-        match self.family_container.get_family_mut::<T>() {
-            Some(family) => {
-                family.components.borrow_mut().add(entity, component);
-                T::group(&self, &entity);
-            },
-            None => {
-                self.family_container.add_family::<T>(family_manager::Family::new());
-                self.cleans.push(Box::new(|comp_manager: &ComponentManager, entity: Entity| {
-                    comp_manager.get_components_mut::<T>().unwrap().remove(&entity);
-                }));
-                self.add_component::<T>(entity, component);
-                return;
-            },
+        self.insert_component(entity, component);
+        T::group(self, entity);
+    }
+
+    ///
+    /// Adds every component of the bundle to an entity and groups it once afterwards.
+    /// See [`Bundle`](crate::Bundle).
+    ///
+    pub fn add_bundle<B: Bundle>(&mut self, entity: &Entity, bundle: B) {
+        bundle.insert(self, entity);
+        B::group(self, entity);
+    }
+
+    /// Adds a component and fires its hooks, without grouping the entity.
+    pub(crate) fn insert_component<T: Component>(&mut self, entity: &Entity, component: T) {
+        self.remove_stale::<T>(entity);
+        let tick = self.change_tick();
+        let family = self.family_mut::<T>();
+        let mut components = family.components.borrow_mut();
+        components.set_change_tick(tick);
+        Self::insert_into(family, &mut components, entity, component);
+    }
+
+    /// Adds a component to each of the entities at the same positions, without grouping them.
+    pub(crate) fn insert_components<T: Component>(&mut self, entities: &[Entity], components: Vec<T>) {
+        for entity in entities {
+            self.remove_stale::<T>(entity);
+        }
+        let tick = self.change_tick();
+        let family = self.family_mut::<T>();
+        let mut set = family.components.borrow_mut();
+        set.set_change_tick(tick);
+        set.reserve(components.len());
+        for (entity, component) in entities.iter().zip(components) {
+            Self::insert_into(family, &mut set, entity, component);
+        }
+    }
+
+    /// Removes the component of another version of the entity, firing its hooks and ungrouping it.
+    fn remove_stale<T: Component>(&self, entity: &Entity) {
+        let stale = self.get_components::<T>().and_then(|set| set.stale_key(entity));
+        if let Some(stale) = stale {
+            self.remove_component::<T>(&stale);
+        }
+    }
+
+    fn insert_into<T: Component>(family: &family_manager::Family<T>, set: &mut sparse_set::SparseSet<Entity, T>, entity: &Entity, component: T) {
+        let replaced = set.add(entity, component);
+        let hooks = &family.hooks;
+        if hooks.on_add.is_empty() && hooks.on_replace.is_empty() {
+            return;
+        }
+        let component = set.get(entity).unwrap();
+        match replaced {
+            Some(old) => hooks.on_replace.iter().for_each(|hook| hook(*entity, &old, component)),
+            None => hooks.on_add.iter().for_each(|hook| hook(*entity, component)),
+        }
+    }
+
+    /// Gets the family of `T`, creating it the first time the component type is used.
+    fn family_mut<T: Component>(&mut self) -> &mut family_manager::Family<T> {
+        if self.family_container.get_family::<T>().is_none() {
+            self.family_container.add_family::<T>(family_manager::Family::new());
+            self.cleans.push(Box::new(|comp_manager: &ComponentManager, entity: Entity| {
+                comp_manager.remove_component::<T>(&entity);
+            }));
+            self.ungroups.push(Box::new(|comp_manager: &ComponentManager, entity: Entity, removed: std::any::TypeId| {
+                T::ungroup(comp_manager, &entity, removed);
+            }));
+            self.removal_updates.push(Box::new(|comp_manager: &ComponentManager| {
+                comp_manager.removed::<T>().unwrap().update();
+            }));
+            self.clears.push((TypeId::of::<T>(), Box::new(|comp_manager: &ComponentManager| {
+                *comp_manager.get_components_mut::<T>().unwrap() = sparse_set::SparseSet::new();
+            })));
+            self.moves.push(Box::new(|from: &ComponentManager, to: &mut ComponentManager, source: &Entity, target: &Entity| {
+                let component = from.remove_component::<T>(source)?;
+                to.insert_component(target, component);
+                Some(T::group as GroupFn)
+            }));
+        }
+        self.family_container.get_family_mut::<T>().unwrap()
+    }
+
+    ///
+    /// Registers a callback fired after a component of type `T` is added to an entity
+    /// that didn't have one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    /// struct Mesh(u32);
+    /// 
+    /// register_components!(Mesh);
+    /// 
+    /// let manager = Manager::new();
+    /// let uploads = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    /// let log = uploads.clone();
+    /// manager.get_comp_manager_mut().on_add::<Mesh, _>(move |_, mesh| log.lock().unwrap().push(mesh.0));
+    /// 
+    /// let entity = manager.add_entity();
+    /// manager.add_component(&entity, Mesh(7));
+    /// assert_eq!(vec![7], *uploads.lock().unwrap());
+    /// ```
+    pub fn on_add<T: Component, F: Fn(Entity, &T) + Send + Sync + 'static>(&mut self, hook: F) {
+        self.family_mut::<T>().hooks.on_add.push(Box::new(hook));
+    }
+
+    ///
+    /// Registers a callback fired after a component of type `T` is overwritten by adding
+    /// another one to the same entity. The callback gets the old and the new component.
+    ///
+    pub fn on_replace<T: Component, F: Fn(Entity, &T, &T) + Send + Sync + 'static>(&mut self, hook: F) {
+        self.family_mut::<T>().hooks.on_replace.push(Box::new(hook));
+    }
+
+    ///
+    /// Registers a callback fired after a component of type `T` is removed from an entity,
+    /// either by removing the component or by killing the entity.
+    ///
+    pub fn on_remove<T: Component, F: Fn(Entity, &T) + Send + Sync + 'static>(&mut self, hook: F) {
+        self.family_mut::<T>().hooks.on_remove.push(Box::new(hook));
+    }
+
+    ///
+    /// Removes a component from an entity and returns it. If the component is part
+    /// of a group, the entity is ungrouped from every set in that group first so the
+    /// grouped part of each set stays packed.
+    /// 
+    /// # Panics
+    /// 
+    /// Panics if any of the components in the same group are borrowed currently.
+    /// [`Manager::remove_component`](crate::Manager::remove_component) borrows the whole
+    /// component sub manager up front instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    /// struct Comp(u32);
+    /// 
+    /// register_components!(Comp);
+    /// 
+    /// let manager = Manager::new();
+    /// 
+    /// let entity = manager.add_entity();
+    /// manager.add_component(&entity, Comp(3));
+    /// 
+    /// let comp_manager = manager.get_comp_manager();
+    /// assert_eq!(3, comp_manager.remove_component::<Comp>(&entity).unwrap().0);
+    /// assert!(!comp_manager.has_component::<Comp>(&entity));
+    /// ```
+    pub fn remove_component<T: Component>(&self, entity: &Entity) -> Option<T> {
+        if !self.has_component::<T>(entity) {
+            return None;
+        }
+        self.ungroup(entity, std::any::TypeId::of::<T>());
+        let component = self.get_components_mut::<T>().unwrap().remove(entity)?;
+        let family = self.family_container.get_family::<T>().unwrap();
+        if self.removal_readers.read().unwrap().contains(&TypeId::of::<T>()) {
+            family.removed.lock().unwrap().send(*entity);
+        }
+        for hook in family.hooks.on_remove.iter() {
+            hook(*entity, &component);
+        }
+        Some(component)
+    }
+
+    /// Ungroups the entity from every group `removed` is a member of.
+    pub(crate) fn ungroup(&self, entity: &Entity, removed: TypeId) {
+        for func in self.ungroups.iter() {
+            func(self, *entity, removed);
         }
     }
 
     pub(crate) fn clean_components(&self, entity: Entity) {
         for func in self.cleans.iter() {
-            func(&self, entity);
+            func(self, entity);
+        }
+    }
+
+    ///
+    /// Lets components of type `T` be copied into snapshots and clones of entities,
+    /// see [`Manager::snapshot`](crate::Manager::snapshot) and [`Manager::clone_entity`](crate::Manager::clone_entity).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    /// #[derive(Clone)]
+    /// struct Pos(i32);
+    /// 
+    /// register_components!(Pos);
+    /// 
+    /// let manager = Manager::new();
+    /// manager.get_comp_manager_mut().register_cloneable::<Pos>();
+    /// ```
+    pub fn register_cloneable<T: Component + Clone>(&mut self) {
+        self.family_mut::<T>();
+        self.cloneables.retain(|cloneable| cloneable.type_id != TypeId::of::<T>());
+        self.cloneables.push(Cloneable {
+            type_id: TypeId::of::<T>(),
+            snapshot: snapshot::snapshot_set::<T>,
+            clone: transfer::clone_component::<T>,
+        });
+    }
+
+    /// Copies the sets of every cloneable component type.
+    pub(crate) fn snapshot_components(&self) -> HashMap<TypeId, ComponentSnapshot> {
+        self.cloneables.iter().map(|cloneable| (cloneable.type_id, (cloneable.snapshot)(self))).collect()
+    }
+
+    ///
+    /// Copies every cloneable component of `source` except those in `skipped` to `target`, without
+    /// grouping it. Returns the group functions of the copied component types.
+    ///
+    pub(crate) fn clone_components(&mut self, source: &Entity, target: &Entity, skipped: &[TypeId]) -> Vec<GroupFn> {
+        let clones: Vec<CloneFn> = self.cloneables.iter()
+            .filter(|cloneable| !skipped.contains(&cloneable.type_id))
+            .map(|cloneable| cloneable.clone)
+            .collect();
+        clones.into_iter().filter_map(|clone| clone(self, source, target)).collect()
+    }
+
+    ///
+    /// Moves every component of `source` to `target` in another comp_manager, without grouping it.
+    /// Returns the group functions of the moved component types.
+    ///
+    pub(crate) fn move_components(&self, to: &mut ComponentManager, source: &Entity, target: &Entity) -> Vec<GroupFn> {
+        self.moves.iter().filter_map(|func| func(self, to, source, target)).collect()
+    }
+
+    ///
+    /// Adds the codec of a component type, replacing the codec previously added for the same type.
+    ///
+    /// # Panics
+    ///
+    /// Panics if another component type uses the same name.
+    ///
+    pub(crate) fn add_codec(&mut self, codec: ComponentCodec) {
+        self.codecs.retain(|other| other.type_id != codec.type_id);
+        assert!(self.codec(&codec.name).is_none(), "Component name {} is used by another component type", codec.name);
+        self.codecs.push(codec);
+    }
+
+    pub(crate) fn codecs(&self) -> &[ComponentCodec] {
+        &self.codecs
+    }
+
+    pub(crate) fn codec(&self, name: &str) -> Option<&ComponentCodec> {
+        self.codecs.iter().find(|codec| codec.name == name)
+    }
+
+    /// Adds the serializer of a component type, see [`ComponentManager::add_codec`].
+    #[cfg(feature = "serde")]
+    pub(crate) fn add_serializer(&mut self, serializer: ComponentSerializer) {
+        self.serializers.retain(|other| other.type_id != serializer.type_id);
+        assert!(self.serializers.iter().all(|other| other.name != serializer.name),
+            "Component name {} is used by another component type", serializer.name);
+        self.serializers.push(serializer);
+    }
+
+    #[cfg(feature = "serde")]
+    pub(crate) fn serializers(&self) -> &[ComponentSerializer] {
+        &self.serializers
+    }
+
+    ///
+    /// Clears the sets of every component type not in `kept` and all relations, before the kept
+    /// sets are replaced by [`ComponentManager::replace_components`]. Returns the cleared types.
+    ///
+    pub(crate) fn clear_components(&mut self, kept: &[TypeId]) -> Vec<TypeId> {
+        let mut cleared = Vec::new();
+        for (type_id, clear) in self.clears.iter() {
+            if !kept.contains(type_id) {
+                clear(self);
+                cleared.push(*type_id);
+            }
+        }
+        self.relations.clear();
+        self.relation_cleans.clear();
+        cleared
+    }
+
+    ///
+    /// Replaces the set of `T`, creating the family if the component type wasn't used yet.
+    /// Grouped entities are ungrouped from every group of a `cleared` component type.
+    ///
+    pub(crate) fn replace_components<T: Component>(&mut self, set: sparse_set::SparseSet<Entity, T>, cleared: &[TypeId]) {
+        let grouped: Vec<Entity> = (0..set.group_len()).map(|i| *set.key_at(i).unwrap()).collect();
+        *self.family_mut::<T>().components.borrow_mut() = set;
+        for removed in cleared {
+            for entity in grouped.iter() {
+                self.ungroup(entity, *removed);
+            }
+        }
+    }
+
+    ///
+    /// Relates `source` to `target`, replacing the value of an existing relation. See [`Relation`].
+    ///
+    pub fn add_relation<R: Relation>(&mut self, source: &Entity, relation: R, target: &Entity) -> Option<R> {
+        if let Entry::Vacant(entry) = self.relations.entry(TypeId::of::<R>()) {
+            entry.insert(Box::new(AtomicRefCell::new(Relations::<R>::default())));
+            self.relation_cleans.push(Box::new(|comp_manager: &ComponentManager, entity: Entity, killed: &mut Vec<Entity>| {
+                let mut relations = comp_manager.relations_mut::<R>().unwrap();
+                relations.remove_source(&entity);
+                match R::ON_TARGET_KILLED {
+                    OnTargetKilled::Remove => {
+                        relations.remove_target(&entity);
+                    },
+                    OnTargetKilled::Kill => killed.extend(relations.remove_target(&entity)),
+                    OnTargetKilled::Dangle => {},
+                }
+            }));
+        }
+        self.relations_mut::<R>().unwrap().insert(source, relation, target)
+    }
+
+    ///
+    /// Gets the relations of type `R`, or None if no relation of that type was added yet.
+    /// 
+    /// # Panics
+    /// 
+    /// Panics if the relations are borrowed mutably.
+    ///
+    pub fn relations<R: Relation>(&self) -> Option<AtomicRef<'_, Relations<R>>> {
+        self.relation_cell::<R>().map(|cell| cell.borrow())
+    }
+
+    ///
+    /// Gets the mutable relations of type `R`, or None if no relation of that type was added yet.
+    /// 
+    /// # Panics
+    /// 
+    /// Panics if the relations are borrowed.
+    ///
+    pub fn relations_mut<R: Relation>(&self) -> Option<AtomicRefMut<'_, Relations<R>>> {
+        self.relation_cell::<R>().map(|cell| cell.borrow_mut())
+    }
+
+    fn relation_cell<R: Relation>(&self) -> Option<&AtomicRefCell<Relations<R>>> {
+        self.relations.get(&TypeId::of::<R>()).and_then(|b| b.downcast_ref::<AtomicRefCell<Relations<R>>>())
+    }
+
+    ///
+    /// Removes the relations of a killed entity. Returns the sources that have to be killed
+    /// because of relations with [`OnTargetKilled::Kill`].
+    ///
+    pub(crate) fn clean_relations(&self, entity: Entity) -> Vec<Entity> {
+        let mut killed = Vec::new();
+        for func in self.relation_cleans.iter() {
+            func(self, entity, &mut killed);
+        }
+        killed
+    }
+
+    ///
+    /// The log of entities that lost their component `T`, or None if the component type was never used.
+    /// See [`RemovedComponents`](crate::RemovedComponents).
+    ///
+    pub(crate) fn removed<T: Component>(&self) -> Option<MutexGuard<'_, Events<Entity>>> {
+        self.family_container.get_family::<T>().map(|family| family.removed.lock().unwrap())
+    }
+
+    /// Starts logging the removals of `T` for a [`RemovedComponents`](crate::RemovedComponents) reader.
+    pub(crate) fn track_removed<T: Component>(&self) {
+        self.removal_readers.write().unwrap().insert(TypeId::of::<T>());
+    }
+
+    /// Drops the removals logged before the previous update, like [`Events::update`].
+    pub(crate) fn update_removed(&self) {
+        for func in self.removal_updates.iter() {
+            func(self);
         }
     }
 
@@ -71,7 +493,7 @@ impl ComponentManager {
     /// 
     /// # Panics
     /// 
-    /// Panics if any other thread adds the same component or borrows the same component as mutable.
+    /// Panics if the component is borrowed mutably, see [`ComponentManager::try_get_components`].
     ///
     /// # Examples
     ///
@@ -90,13 +512,33 @@ impl ComponentManager {
     /// 
     /// comp_manager.get_components::<Comp>();
     /// ```
-    pub fn get_components<T: Component>(&self) -> Option<View<T>> {
-        match self.family_container.get_family::<T>() {
-            Some(family) =>  {
-                Some(family.components.borrow())
-            },
-            None => None,
-        }
+    pub fn get_components<T: Component>(&self) -> Option<View<'_, T>> {
+        self.try_get_components::<T>().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    ///
+    /// Gets the sparse_set of a certain component, or an error if it is borrowed mutably.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    /// 
+    /// struct Comp {}
+    /// 
+    /// register_components!(Comp);
+    /// 
+    /// let manager = Manager::new();
+    /// 
+    /// let entity = manager.add_entity();
+    /// manager.add_component(&entity, Comp {});
+    /// let comp_manager = manager.get_comp_manager();
+    /// 
+    /// let set = comp_manager.get_components_mut::<Comp>();
+    /// assert!(comp_manager.try_get_components::<Comp>().is_err());
+    /// ```
+    pub fn try_get_components<T: Component>(&self) -> Result<Option<View<'_, T>>, BorrowError> {
+        self.family_container.get_family::<T>().map(|family| family.components.try_borrow()).transpose()
     }
 
     ///
@@ -104,7 +546,7 @@ impl ComponentManager {
     /// 
     /// # Panics
     /// 
-    /// Panics if any other thread adds or borrows the same component.
+    /// Panics if the component is borrowed, see [`ComponentManager::try_get_components_mut`].
     ///
     /// # Examples
     ///
@@ -123,13 +565,167 @@ impl ComponentManager {
     /// 
     /// comp_manager.get_components_mut::<Comp>();
     /// ```
-    pub fn get_components_mut<T: Component>(&self) -> Option<ViewMut<T>> {
-        match self.family_container.get_family::<T>() {
-            Some(family) =>  {
-                Some(family.components.borrow_mut())
-            },
-            None => None,
-        }
+    pub fn get_components_mut<T: Component>(&self) -> Option<ViewMut<'_, T>> {
+        self.try_get_components_mut::<T>().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    ///
+    /// Gets the mutable sparse_set of a certain component, or an error if it is borrowed.
+    /// Entries added or accessed mutably through it are stamped with the current change tick.
+    ///
+    pub fn try_get_components_mut<T: Component>(&self) -> Result<Option<ViewMut<'_, T>>, BorrowError> {
+        let tick = self.change_tick();
+        self.family_container.get_family::<T>()
+            .map(|family| family.components.try_borrow_mut().map(|mut set| {
+                set.set_change_tick(tick);
+                set
+            }))
+            .transpose()
+    }
+
+    ///
+    /// Returns the amount of entities currently grouped in the set of the given component.
+    /// For a full group this is the amount of entities owning every component of the group.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    /// 
+    /// struct Pos(i32);
+    /// struct Vel(i32);
+    /// 
+    /// group!(Pos, Vel);
+    /// 
+    /// let manager = Manager::new();
+    /// 
+    /// let entity = manager.add_entity();
+    /// manager.add_component(&entity, Pos(0));
+    /// assert_eq!(0, manager.get_comp_manager().group_len::<Pos>());
+    /// 
+    /// manager.add_component(&entity, Vel(2));
+    /// assert_eq!(1, manager.get_comp_manager().group_len::<Pos>());
+    /// ```
+    pub fn group_len<T: Component>(&self) -> usize {
+        self.get_components::<T>().map_or(0, |set| set.group_len())
+    }
+
+    ///
+    /// Queries all entities having every component in `Q`, yielding `(Entity, ..components)` tuples.
+    /// Iteration is driven by the smallest set while the others are probed.
+    /// All sets are borrowed once, when the query is created.
+    /// 
+    /// # Panics
+    /// 
+    /// Panics if any of the components are borrowed mutably elsewhere, or if the same
+    /// component is queried both mutably and immutably. See [`ComponentManager::try_query`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    /// 
+    /// struct Pos(i32);
+    /// struct Vel(i32);
+    /// 
+    /// register_components!(Pos, Vel);
+    /// 
+    /// let manager = Manager::new();
+    /// 
+    /// let entity = manager.add_entity();
+    /// manager.add_component(&entity, Pos(0));
+    /// manager.add_component(&entity, Vel(2));
+    /// let comp_manager = manager.get_comp_manager();
+    /// 
+    /// let mut query = comp_manager.query::<(&mut Pos, &Vel)>();
+    /// for (_, pos, vel) in query.iter() {
+    ///     pos.0 += vel.0;
+    /// }
+    /// ```
+    pub fn query<Q: QueryData>(&self) -> Query<'_, Q> {
+        self.try_query::<Q>().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    ///
+    /// Queries all entities having every component in `Q`, or returns an error if any
+    /// of the components can't be borrowed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    /// 
+    /// struct Pos(i32);
+    /// 
+    /// register_components!(Pos);
+    /// 
+    /// let manager = Manager::new();
+    /// 
+    /// let entity = manager.add_entity();
+    /// manager.add_component(&entity, Pos(0));
+    /// let comp_manager = manager.get_comp_manager();
+    /// 
+    /// let query = comp_manager.query::<(&mut Pos,)>();
+    /// assert!(comp_manager.try_query::<(&Pos,)>().is_err());
+    /// ```
+    pub fn try_query<Q: QueryData>(&self) -> Result<Query<'_, Q>, BorrowError> {
+        Query::new(self)
+    }
+
+    ///
+    /// Queries all entities having every component in `Q` and matching the filter `F`.
+    /// Filters are [`With`](crate::With), [`Without`](crate::Without), [`Added`](crate::Added),
+    /// [`Changed`](crate::Changed), [`Or`](crate::Or) and tuples of those. Components can be fetched optionally with `Option<&T>`.
+    /// 
+    /// # Panics
+    /// 
+    /// Panics if any of the components are borrowed mutably elsewhere, or if the same
+    /// component is queried or filtered both mutably and immutably.
+    /// See [`ComponentManager::try_query_filtered`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    /// 
+    /// struct Pos(i32);
+    /// struct Vel(i32);
+    /// struct Sprite;
+    /// struct Frozen;
+    /// 
+    /// register_components!(Pos, Vel, Sprite, Frozen);
+    /// 
+    /// let manager = Manager::new();
+    /// 
+    /// let moving = manager.add_entity();
+    /// manager.add_component(&moving, Pos(0));
+    /// manager.add_component(&moving, Vel(2));
+    /// manager.add_component(&moving, Sprite);
+    /// 
+    /// let frozen = manager.add_entity();
+    /// manager.add_component(&frozen, Pos(0));
+    /// manager.add_component(&frozen, Vel(2));
+    /// manager.add_component(&frozen, Frozen);
+    /// 
+    /// let comp_manager = manager.get_comp_manager();
+    /// 
+    /// let mut query = comp_manager.query_filtered::<(&mut Pos, &Vel, Option<&Sprite>), Without<Frozen>>();
+    /// for (entity, pos, vel, sprite) in query.iter() {
+    ///     assert_eq!(entity, moving);
+    ///     assert!(sprite.is_some());
+    ///     pos.0 += vel.0;
+    /// }
+    /// ```
+    pub fn query_filtered<Q: QueryData, F: QueryFilter>(&self) -> Query<'_, Q, F> {
+        self.try_query_filtered::<Q, F>().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    ///
+    /// Queries all entities having every component in `Q` and matching the filter `F`,
+    /// or returns an error if any of the components can't be borrowed.
+    ///
+    pub fn try_query_filtered<Q: QueryData, F: QueryFilter>(&self) -> Result<Query<'_, Q, F>, BorrowError> {
+        Query::new(self)
     }
 
     ///
@@ -137,8 +733,7 @@ impl ComponentManager {
     /// 
     /// # Panics
     /// 
-    /// Panics if any other thread adds the same component or borrows the same component as mutable.
-    /// Will be solved soon when Manager becomes threadsafe.
+    /// Panics if the component is borrowed mutably.
     ///
     /// # Examples
     ///
@@ -171,6 +766,7 @@ macro_rules! register_components {
         $(
         impl $crate::Component for $component {
             fn group(_: &$crate::ComponentManager, _: &$crate::Entity) { }
+            fn group_batch(_: &$crate::ComponentManager, _: &[$crate::Entity]) { }
         }
         )*
     };
@@ -204,6 +800,44 @@ macro_rules! group_imlp {
                     )+
                 }
             }
+
+            fn group_batch(cm: &$crate::ComponentManager, entities: &[$crate::Entity]) {
+                let mut ready = entities.to_vec();
+                $(
+                if let Some(set) = cm.get_components::<$queue>() {
+                    ready.retain(|entity| set.contains(entity));
+                } else {
+                    return;
+                }
+                )+
+                let mut set = cm.get_components_mut::<$head>().unwrap();
+                ready.iter().for_each(|entity| set.group(entity));
+                drop(set);
+                $(
+                let mut set = cm.get_components_mut::<$queue>().unwrap();
+                ready.iter().for_each(|entity| set.group(entity));
+                drop(set);
+                )+
+            }
+
+            fn ungroup(cm: &$crate::ComponentManager, entity: &$crate::Entity, removed: std::any::TypeId) {
+                if removed == std::any::TypeId::of::<$head>() $(|| removed == std::any::TypeId::of::<$queue>())+ {
+                    if let Some(mut set) = cm.get_components_mut::<$head>() {
+                        if set.contains(entity) {
+                            set.ungroup(entity);
+                        }
+                    }
+                }
+            }
+
+            fn grouped_by(types: &[std::any::TypeId]) -> bool {
+                let group = [std::any::TypeId::of::<$head>(), $(std::any::TypeId::of::<$queue>()),+];
+                types.len() == group.len() && group.iter().all(|member| types.contains(member))
+            }
+
+            fn group_members() -> Vec<std::any::TypeId> {
+                vec![std::any::TypeId::of::<$head>(), $(std::any::TypeId::of::<$queue>()),+]
+            }
         }
     };
 
@@ -214,6 +848,31 @@ macro_rules! group_imlp {
                     cm.get_components_mut::<$head>().unwrap().group(entity);
                 }
             }
+
+            fn group_batch(cm: &$crate::ComponentManager, entities: &[$crate::Entity]) {
+                let mut ready = entities.to_vec();
+                $(
+                if let Some(set) = cm.get_components::<$queue>() {
+                    ready.retain(|entity| set.contains(entity));
+                } else {
+                    return;
+                }
+                )+
+                let mut set = cm.get_components_mut::<$head>().unwrap();
+                ready.iter().for_each(|entity| set.group(entity));
+            }
+
+            fn ungroup(cm: &$crate::ComponentManager, entity: &$crate::Entity, removed: std::any::TypeId) {
+                if removed == std::any::TypeId::of::<$head>() $(|| removed == std::any::TypeId::of::<$queue>())+ {
+                    if let Some(mut set) = cm.get_components_mut::<$head>() {
+                        if set.contains(entity) {
+                            set.ungroup(entity);
+                        }
+                    }
+                }
+            }
+
+            fn grouped_by(_: &[std::any::TypeId]) -> bool { false }
         }
     };
 }
@@ -235,6 +894,182 @@ macro_rules! group_rec {
     };
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Pos(i32);
+    struct Vel(i32);
+    struct Hp;
+    struct Sprite(i32);
+
+    group!(Pos, Vel);
+    register_components!(Hp);
+    group_partial!(Sprite => Hp);
+
+    /// Asserts that every entity owning both Pos and Vel sits at the same index at the front of both sets.
+    fn assert_lockstep(cm: &ComponentManager) {
+        let pos = cm.get_components::<Pos>().unwrap();
+        let vel = cm.get_components::<Vel>().unwrap();
+        let grouped = (0..pos.len()).filter(|i| vel.contains(pos.key_at(*i).unwrap())).count();
+        for i in 0..grouped {
+            assert_eq!(pos.key_at(i), vel.key_at(i));
+            assert!(vel.contains(pos.key_at(i).unwrap()));
+        }
+    }
+
+    #[test]
+    fn remove_ungroups_siblings() {
+        let mut cm = ComponentManager::new();
+        for i in 0..5 {
+            cm.add_component(&Entity::new(i, 0), Pos(i as i32));
+            cm.add_component(&Entity::new(i, 0), Vel(i as i32));
+        }
+
+        assert_eq!(cm.remove_component::<Pos>(&Entity::new(1, 0)).unwrap().0, 1);
+
+        assert!(!cm.has_component::<Pos>(&Entity::new(1, 0)));
+        assert!(cm.has_component::<Vel>(&Entity::new(1, 0)));
+        let vel = cm.get_components::<Vel>().unwrap();
+        let pos = cm.get_components::<Pos>().unwrap();
+        for i in 0..4 {
+            assert_eq!(pos.key_at(i), vel.key_at(i));
+            assert_ne!(*pos.key_at(i).unwrap(), Entity::new(1, 0));
+        }
+        assert_eq!(*vel.key_at(4).unwrap(), Entity::new(1, 0));
+        assert_eq!(vel.get(&Entity::new(1, 0)).unwrap().0, 1);
+    }
+
+    #[test]
+    fn remove_and_regroup() {
+        let mut cm = ComponentManager::new();
+        for i in 0..4 {
+            cm.add_component(&Entity::new(i, 0), Pos(i as i32));
+            cm.add_component(&Entity::new(i, 0), Vel(i as i32));
+        }
+        cm.remove_component::<Vel>(&Entity::new(0, 0));
+        cm.remove_component::<Pos>(&Entity::new(2, 0));
+        cm.add_component(&Entity::new(0, 0), Vel(10));
+        assert_lockstep(&cm);
+
+        let pos = cm.get_components::<Pos>().unwrap();
+        assert_eq!(pos.get(&Entity::new(0, 0)).unwrap().0, 0);
+        assert_eq!(pos.get(&Entity::new(3, 0)).unwrap().0, 3);
+    }
+
+    #[test]
+    fn remove_partial_requirement() {
+        let mut cm = ComponentManager::new();
+        cm.add_component(&Entity::new(0, 0), Sprite(0));
+        cm.add_component(&Entity::new(1, 0), Hp);
+        cm.add_component(&Entity::new(1, 0), Sprite(1));
+
+        assert_eq!(*cm.get_components::<Sprite>().unwrap().key_at(0).unwrap(), Entity::new(1, 0));
+
+        cm.remove_component::<Hp>(&Entity::new(1, 0));
+        cm.add_component(&Entity::new(2, 0), Hp);
+        cm.add_component(&Entity::new(2, 0), Sprite(2));
+
+        let sprite = cm.get_components::<Sprite>().unwrap();
+        assert_eq!(*sprite.key_at(0).unwrap(), Entity::new(2, 0));
+        assert_eq!(sprite.get(&Entity::new(1, 0)).unwrap().0, 1);
+    }
+
+    #[test]
+    fn remove_missing() {
+        let mut cm = ComponentManager::new();
+        cm.add_component(&Entity::new(0, 0), Hp);
+
+        assert!(cm.remove_component::<Hp>(&Entity::new(1, 0)).is_none());
+        assert!(cm.remove_component::<Pos>(&Entity::new(0, 0)).is_none());
+    }
+
+    #[test]
+    fn clean_keeps_groups() {
+        let mut cm = ComponentManager::new();
+        for i in 0..4 {
+            cm.add_component(&Entity::new(i, 0), Pos(i as i32));
+            cm.add_component(&Entity::new(i, 0), Vel(i as i32));
+        }
+        cm.clean_components(Entity::new(0, 0));
+        assert_lockstep(&cm);
+
+        let pos = cm.get_components::<Pos>().unwrap();
+        assert_eq!(pos.len(), 3);
+        assert_eq!(pos.get(&Entity::new(3, 0)).unwrap().0, 3);
+    }
+
+    #[test]
+    fn hooks() {
+        use std::sync::{Arc, Mutex};
+
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut cm = ComponentManager::new();
+        let added = log.clone();
+        cm.on_add::<Pos, _>(move |e, pos| added.lock().unwrap().push(("add", e.get_index(), pos.0)));
+        let replaced = log.clone();
+        cm.on_replace::<Pos, _>(move |e, old, new| replaced.lock().unwrap().push(("replace", e.get_index(), old.0 * 10 + new.0)));
+        let removed = log.clone();
+        cm.on_remove::<Pos, _>(move |e, pos| removed.lock().unwrap().push(("remove", e.get_index(), pos.0)));
+
+        let e0 = Entity::new(0, 0);
+        let e1 = Entity::new(1, 0);
+        cm.add_component(&e0, Pos(1));
+        cm.add_component(&e0, Vel(1));
+        cm.add_component(&e1, Pos(2));
+        cm.add_component(&e0, Pos(3));
+        cm.remove_component::<Pos>(&e1);
+        cm.clean_components(e0);
+
+        assert_eq!(*log.lock().unwrap(), vec![
+            ("add", 0, 1),
+            ("add", 1, 2),
+            ("replace", 0, 13),
+            ("remove", 1, 2),
+            ("remove", 0, 3),
+        ]);
+    }
+
+    #[test]
+    fn add_removes_stale_version() {
+        use std::sync::{Arc, Mutex};
+
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut cm = ComponentManager::new();
+        let removed = log.clone();
+        cm.on_remove::<Pos, _>(move |e, pos| removed.lock().unwrap().push((e, pos.0)));
+
+        let old = Entity::new(1, 0);
+        let new = Entity::new(1, 1);
+        cm.add_component(&Entity::new(0, 0), Pos(0));
+        cm.add_component(&Entity::new(0, 0), Vel(0));
+        cm.add_component(&old, Pos(1));
+        cm.add_component(&old, Vel(1));
+        cm.add_component(&new, Pos(2));
+
+        assert_eq!(*log.lock().unwrap(), vec![(old, 1)]);
+        assert!(!cm.has_component::<Pos>(&old) && cm.has_component::<Vel>(&old));
+        assert_eq!(cm.group_len::<Pos>(), 1);
+        assert_lockstep(&cm);
+        assert_eq!(cm.get_components::<Pos>().unwrap().get(&new).unwrap().0, 2);
+    }
+
+    #[test]
+    fn replace_keeps_group() {
+        let mut cm = ComponentManager::new();
+        for i in 0..3 {
+            cm.add_component(&Entity::new(i, 0), Pos(i as i32));
+            cm.add_component(&Entity::new(i, 0), Vel(i as i32));
+        }
+        cm.add_component(&Entity::new(1, 0), Pos(7));
+        cm.add_component(&Entity::new(1, 0), Vel(7));
+
+        assert_eq!(cm.group_len::<Pos>(), 3);
+        assert_lockstep(&cm);
+        assert_eq!(cm.get_components::<Pos>().unwrap().get(&Entity::new(1, 0)).unwrap().0, 7);
+    }
+}
+
 // pub struct View<'l, T: std::any::Any> {
 //     set_ref: std::cell::Ref<'l, sparse_set::SparseSet<T>>,
 // }
@@ -0,0 +1,245 @@
+use std::sync::Mutex;
+
+use crate::{Bundle, Component, Entity, Manager};
+
+pub(crate) type Command = Box<dyn FnOnce(&Manager) + Send>;
+
+///
+/// Buffer of structural changes to apply to a manager later. Systems get one to
+/// spawn, kill and change entities while the components are borrowed. The manager
+/// applies it in order after the system returns.
+///
+/// Spawned entities are reserved right away, so they can be used in later commands
+/// of the same buffer. They get their components when the buffer is applied, a buffer
+/// dropped without being applied kills them again.
+///
+/// # Examples
+///
+/// ```
+/// use rust_ecs::*;
+/// struct Comp(u32);
+///
+/// register_components!(Comp);
+///
+/// let manager = Manager::new();
+///
+/// let mut commands = Commands::new(&manager);
+/// let entity = commands.spawn();
+/// commands.add_component(&entity, Comp(2));
+/// assert!(!manager.get_comp_manager().has_component::<Comp>(&entity));
+///
+/// commands.apply();
+/// assert!(manager.get_comp_manager().has_component::<Comp>(&entity));
+/// ```
+pub struct Commands<'m> {
+    manager: &'m Manager,
+    queue: Vec<Command>,
+    // Entities reserved by spawn, killed on drop if the buffer was never applied.
+    spawned: Vec<Entity>,
+    // Commands handed to typed systems move their queue here when dropped.
+    sink: Option<&'m Mutex<Vec<Command>>>,
+}
+
+impl<'m> Commands<'m> {
+    pub fn new(manager: &'m Manager) -> Self {
+        Commands {
+            manager,
+            queue: Vec::new(),
+            spawned: Vec::new(),
+            sink: None,
+        }
+    }
+
+    /// Creates a buffer that moves its commands to `sink` when dropped instead of losing them.
+    pub(crate) fn with_sink(manager: &'m Manager, sink: &'m Mutex<Vec<Command>>) -> Self {
+        Commands {
+            manager,
+            queue: Vec::new(),
+            spawned: Vec::new(),
+            sink: Some(sink),
+        }
+    }
+
+    pub(crate) fn manager(&self) -> &'m Manager {
+        self.manager
+    }
+
+    /// Queues already recorded commands after the ones in this buffer.
+    pub(crate) fn append(&mut self, commands: &mut Vec<Command>) {
+        self.queue.append(commands);
+    }
+
+    ///
+    /// Reserves a new entity and returns its identifier.
+    /// The entity is killed again if the buffer is dropped without being applied.
+    ///
+    pub fn spawn(&mut self) -> Entity {
+        let entity = self.manager.add_entity();
+        self.spawned.push(entity);
+        entity
+    }
+
+    ///
+    /// Kills the entity and removes all of its components when applied.
+    ///
+    pub fn kill_entity(&mut self, entity: Entity) {
+        self.queue.push(Box::new(move |manager: &Manager| {
+            manager.kill_entity(entity);
+        }));
+    }
+
+    ///
+    /// Adds a component to the entity when applied, if the entity is still alive by then.
+    ///
+    pub fn add_component<T: Component>(&mut self, entity: &Entity, component: T) {
+        let entity = *entity;
+        self.queue.push(Box::new(move |manager: &Manager| {
+            if manager.entity_alive(entity) {
+                manager.add_component(&entity, component);
+            }
+        }));
+    }
+
+    ///
+    /// Adds every component of the bundle to the entity when applied, if the entity is still alive by then.
+    ///
+    pub fn add_bundle<B: Bundle>(&mut self, entity: &Entity, bundle: B) {
+        let entity = *entity;
+        self.queue.push(Box::new(move |manager: &Manager| {
+            if manager.entity_alive(entity) {
+                manager.add_bundle(&entity, bundle);
+            }
+        }));
+    }
+
+    ///
+    /// Removes a component from the entity when applied.
+    ///
+    pub fn remove_component<T: Component>(&mut self, entity: &Entity) {
+        let entity = *entity;
+        self.queue.push(Box::new(move |manager: &Manager| {
+            manager.remove_component::<T>(&entity);
+        }));
+    }
+
+    /// Returns the amount of commands waiting to be applied.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    ///
+    /// Applies all commands in the order they were recorded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the components of the manager are borrowed.
+    pub fn apply(mut self) {
+        self.spawned.clear();
+        for command in std::mem::take(&mut self.queue) {
+            command(self.manager);
+        }
+    }
+}
+
+impl Drop for Commands<'_> {
+    fn drop(&mut self) {
+        if let Some(sink) = self.sink {
+            sink.lock().unwrap().append(&mut self.queue);
+            return;
+        }
+        for entity in self.spawned.drain(..).rev() {
+            // Nothing of this buffer reached the entity, so it has no components to remove
+            // when the comp_manager can't be borrowed.
+            if self.manager.try_kill_entity(entity).is_err() {
+                self.manager.ent_handler.lock().unwrap().kill_entity(entity);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Pos(i32);
+    struct Vel(i32);
+
+    group!(Pos, Vel);
+
+    #[test]
+    fn spawn_reserves() {
+        let manager = Manager::new();
+        let mut commands = Commands::new(&manager);
+        let e0 = commands.spawn();
+        let e1 = commands.spawn();
+
+        assert_ne!(e0, e1);
+        assert!(manager.entity_alive(e1));
+        assert!(commands.is_empty());
+    }
+
+    #[test]
+    fn drop_kills_spawned() {
+        let manager = Manager::new();
+        let applied = {
+            let mut commands = Commands::new(&manager);
+            let entity = commands.spawn();
+            commands.apply();
+            entity
+        };
+        let dropped = {
+            let mut commands = Commands::new(&manager);
+            let entity = commands.spawn();
+            commands.add_component(&entity, Pos(1));
+            entity
+        };
+        let borrowed = {
+            let _cm = manager.get_comp_manager();
+            let mut commands = Commands::new(&manager);
+            commands.spawn()
+        };
+
+        assert!(manager.entity_alive(applied));
+        assert!(!manager.entity_alive(dropped));
+        assert!(!manager.entity_alive(borrowed));
+    }
+
+    #[test]
+    fn apply_in_order() {
+        let manager = Manager::new();
+        let entity = manager.add_entity();
+        manager.add_component(&entity, Pos(0));
+
+        let mut commands = Commands::new(&manager);
+        let spawned = commands.spawn();
+        commands.add_component(&spawned, Pos(1));
+        commands.add_component(&spawned, Vel(1));
+        commands.remove_component::<Pos>(&entity);
+        commands.add_component(&entity, Vel(2));
+        assert_eq!(commands.len(), 4);
+        commands.apply();
+
+        let cm = manager.get_comp_manager();
+        assert!(!cm.has_component::<Pos>(&entity));
+        assert_eq!(cm.get_components::<Vel>().unwrap().get(&entity).unwrap().0, 2);
+        assert_eq!(cm.group_len::<Pos>(), 1);
+        assert_eq!(cm.get_components::<Pos>().unwrap().get(&spawned).unwrap().0, 1);
+    }
+
+    #[test]
+    fn add_after_kill() {
+        let manager = Manager::new();
+        let mut commands = Commands::new(&manager);
+        let entity = commands.spawn();
+        commands.kill_entity(entity);
+        commands.add_component(&entity, Pos(1));
+        commands.apply();
+
+        assert!(!manager.entity_alive(entity));
+        assert!(!manager.get_comp_manager().has_component::<Pos>(&entity));
+    }
+}
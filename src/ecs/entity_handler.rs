@@ -1,19 +1,56 @@
 
-const VERSION_MASK: u32 = 16383;
+/// Underlying integer of an entity. 32 bits split into an 18 bit index and a 14 bit version,
+/// or with the `entity64` feature 64 bits split into a 32 bit index and a 32 bit version.
+#[cfg(not(feature = "entity64"))]
+pub type EntityId = u32;
+#[cfg(not(feature = "entity64"))]
+const VERSION_BITS: u32 = 14;
+
+#[cfg(feature = "entity64")]
+pub type EntityId = u64;
+#[cfg(feature = "entity64")]
+const VERSION_BITS: u32 = 32;
+
+const VERSION_MASK: EntityId = (1 << VERSION_BITS) - 1;
+
+/// Highest version an entity can have before its slot overflows.
+#[allow(clippy::unnecessary_cast)]
+pub const MAX_VERSION: u32 = VERSION_MASK as u32;
+/// Index that is never handed out. Marks retired slots.
+#[allow(clippy::unnecessary_cast)]
+const NULL_INDEX: u32 = (EntityId::MAX >> VERSION_BITS) as u32;
 
-#[derive(Hash, Clone, Copy)]
+///
+/// What happens to an entity slot when it is killed at [`MAX_VERSION`].
+/// 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum VersionOverflow {
+	/// The slot is never reused, so old handles can never be mistaken for new entities.
+	#[default]
+	Retire,
+	/// The version starts over from 0. Very old handles might become alive again.
+	Wrap,
+}
+
+#[derive(Hash, Clone, Copy, PartialEq, Eq)]
 pub struct Entity {
-	id: u32,
+	id: EntityId,
 }
 
 impl Entity {
 	
 	pub(crate) fn new(index: u32, version: u32) -> Self {
 		Self {
-			id: (index << 18) + version,
+			id: ((index as EntityId) << VERSION_BITS) | version as EntityId,
 		}
 	}
 
+	/// Creates an entity from parts read from outside, or None if they don't fit into an [`EntityId`].
+	#[allow(clippy::absurd_extreme_comparisons)]
+	pub(crate) fn try_new(index: u32, version: u32) -> Option<Self> {
+		(index <= NULL_INDEX && version <= MAX_VERSION).then(|| Self::new(index, version))
+	}
+
 	///
     /// Gets the index of the entity.
     /// 
@@ -29,8 +66,9 @@ impl Entity {
     /// assert_eq!(0, entity1.get_index());
     /// assert_eq!(1, entity2.get_index());
     /// ```
+	#[allow(clippy::unnecessary_cast)]
 	pub fn get_index(&self) -> u32 {
-		self.id >> 18
+		(self.id >> VERSION_BITS) as u32
 	}
 
 	///
@@ -47,13 +85,20 @@ impl Entity {
     /// assert_eq!(0, entity1.get_version());
     /// assert_eq!(0, entity2.get_version());
     /// ```
+	#[allow(clippy::unnecessary_cast)]
 	pub fn get_version(&self) -> u32 {
-		self.id & VERSION_MASK
+		(self.id & VERSION_MASK) as u32
+	}
+}
+
+impl crate::sparse_set::SparseKey for Entity {
+	fn sparse_index(&self) -> usize {
+		self.get_index() as usize
 	}
 }
 
 impl std::ops::Deref for Entity {
-	type Target = u32;
+	type Target = EntityId;
 
     fn deref(&self) -> &Self::Target {
         &self.id
@@ -69,32 +114,40 @@ impl std::fmt::Debug for Entity {
     }
 }
 
-impl PartialEq for Entity {
-    fn eq(&self, other: &Self) -> bool {
-        self.id == other.id
-    }
-}
-
-impl Eq for Entity {}
-
 ///
 /// Submodule for handling of entity creation and deletion.
 /// 
+/// Killed slots form a free list through the index of the stored entity,
+/// starting at `head_index`. `killed` is the length of the free list.
+/// 
+#[derive(Clone)]
 pub struct EntityHandler {
 	entities: Vec<Entity>,
 	head_index: u32,
 	killed: usize,
+	overflow: VersionOverflow,
 }
 
 impl EntityHandler {
 	pub fn new() -> Self {
+		Self::with_overflow(VersionOverflow::default())
+	}
+
+	pub fn with_overflow(overflow: VersionOverflow) -> Self {
 		Self {
 			entities: Vec::new(),
 			head_index: 0,
 			killed: 0,
+			overflow,
 		}
 	}
 
+	///
+	/// Creates a new entity, reusing a killed slot if there is one.
+	/// 
+	/// # Panics
+	/// 
+	/// Panics if every index is in use.
 	pub fn new_entity(&mut self) -> Entity {
 		if self.killed > 0 {
 			let head_entity = *self.entities.get(self.head_index as usize).unwrap();
@@ -102,22 +155,72 @@ impl EntityHandler {
 
 			*self.entities.get_mut(self.head_index as usize).unwrap() = new_ident;
 			self.head_index = head_entity.get_index();
-			//Dear god this code...
-			return new_ident;
+			self.killed -= 1;
+			new_ident
 		} else {
 			let index = self.entities.len() as u32;
+			assert!(index < NULL_INDEX, "No free entity indices left");
 			let new_ident = Entity::new(index, 0);
 			self.entities.push(new_ident);
-			return new_ident;
+			new_ident
 		}
 	}
 
+	///
+	/// Reserves room for at least `additional` more entities, counting the killed slots that get reused first.
+	pub fn reserve(&mut self, additional: usize) {
+		self.entities.reserve(additional.saturating_sub(self.killed));
+	}
+
+	///
+	/// Kills an alive entity and frees its slot for reuse. Killing an entity at
+	/// [`MAX_VERSION`] follows the overflow policy of the handler.
 	pub fn kill_entity(&mut self, entity: Entity) {
-		if let Some(elem) = self.entities.get_mut(entity.get_index() as usize) {
-			*elem = Entity::new(self.head_index, entity.get_version() + 1);
-			self.head_index = entity.get_index();
-			self.killed += 1;
+		if !self.is_alive(entity) {
+			return;
 		}
+		let index = entity.get_index() as usize;
+		if entity.get_version() == MAX_VERSION && self.overflow == VersionOverflow::Retire {
+			self.entities[index] = Entity::new(NULL_INDEX, MAX_VERSION);
+			return;
+		}
+		let version = if entity.get_version() == MAX_VERSION { 0 } else { entity.get_version() + 1 };
+		self.entities[index] = Entity::new(self.head_index, version);
+		self.head_index = entity.get_index();
+		self.killed += 1;
+	}
+
+	///
+	/// Rebuilds a handler from the parts returned by [`EntityHandler::slots`] and [`EntityHandler::free_list`].
+	/// Returns None if the free list doesn't match the slots.
+	pub(crate) fn from_parts(entities: Vec<Entity>, head_index: u32, killed: usize, overflow: VersionOverflow) -> Option<Self> {
+		let mut free = vec![false; entities.len()];
+		let mut index = head_index;
+		for _ in 0..killed {
+			let slot = free.get_mut(index as usize)?;
+			if *slot {
+				return None;
+			}
+			*slot = true;
+			index = entities[index as usize].get_index();
+		}
+		let consistent = entities.iter().zip(free.iter()).enumerate()
+			.all(|(i, (entity, free))| *free || entity.get_index() == i as u32 || entity.get_index() == NULL_INDEX);
+		consistent.then_some(Self { entities, head_index, killed, overflow })
+	}
+
+	/// Every slot, holding either an alive entity, the next index of the free list or a retired marker.
+	pub(crate) fn slots(&self) -> &[Entity] {
+		&self.entities
+	}
+
+	/// The head index and the length of the free list.
+	pub(crate) fn free_list(&self) -> (u32, usize) {
+		(self.head_index, self.killed)
+	}
+
+	pub(crate) fn overflow(&self) -> VersionOverflow {
+		self.overflow
 	}
 
 	pub fn is_alive(&self, entity: Entity) -> bool {
@@ -125,7 +228,13 @@ impl EntityHandler {
 			//Compares version, but inefficient to do to_version
 			return *identity == entity;
 		}
-		return false;
+		false
+	}
+}
+
+impl Default for EntityHandler {
+	fn default() -> Self {
+		Self::new()
 	}
 }
 
@@ -194,6 +303,70 @@ mod tests {
 
 		assert_eq!(e.id, 20);
 	}
+
+	#[test]
+	fn entity_max_version() {
+		let e = Entity::new(3, MAX_VERSION);
+
+		assert_eq!(e.get_index(), 3);
+		assert_eq!(e.get_version(), MAX_VERSION);
+		assert_eq!(Entity::new(NULL_INDEX, 0).get_index(), NULL_INDEX);
+	}
+
+	#[test]
+	fn respawn_unique() {
+		let mut handler = EntityHandler::new();
+		let e0 = handler.new_entity();
+		handler.kill_entity(e0);
+		let e1 = handler.new_entity();
+		let e2 = handler.new_entity();
+
+		assert_eq!(e1.get_index(), 0);
+		assert_eq!(e1.get_version(), 1);
+		assert_eq!(e2.get_index(), 1);
+		assert!(handler.is_alive(e1));
+		assert!(handler.is_alive(e2));
+	}
+
+	#[test]
+	fn kill_twice() {
+		let mut handler = EntityHandler::new();
+		let e0 = handler.new_entity();
+		handler.kill_entity(e0);
+		handler.kill_entity(e0);
+
+		assert_eq!(handler.killed, 1);
+		handler.new_entity();
+		assert_eq!(handler.new_entity().get_index(), 1);
+	}
+
+	#[test]
+	fn overflow_retire() {
+		let mut handler = EntityHandler::with_overflow(VersionOverflow::Retire);
+		handler.new_entity();
+		let e1 = Entity::new(1, MAX_VERSION);
+		handler.entities.push(e1);
+		handler.kill_entity(e1);
+
+		assert!(!handler.is_alive(e1));
+		assert!(!handler.is_alive(Entity::new(1, 0)));
+		assert_eq!(handler.killed, 0);
+		assert_eq!(handler.new_entity().get_index(), 2);
+	}
+
+	#[test]
+	fn overflow_wrap() {
+		let mut handler = EntityHandler::with_overflow(VersionOverflow::Wrap);
+		handler.new_entity();
+		let e1 = Entity::new(1, MAX_VERSION);
+		handler.entities.push(e1);
+		handler.kill_entity(e1);
+
+		assert!(!handler.is_alive(e1));
+		let e2 = handler.new_entity();
+		assert_eq!(e2.get_index(), 1);
+		assert_eq!(e2.get_version(), 0);
+	}
 }
 
 #[cfg(all(feature = "unstable", test))]
@@ -0,0 +1,291 @@
+use std::marker::PhantomData;
+
+use crate::systems::{Access, SystemContext, SystemParam};
+use crate::{Component, Entity, Manager, Res, ResMut};
+
+///
+/// Double buffered channel of events of type `E`, stored as a resource of the manager.
+/// Events live until the second [`Events::update`] after they were sent, which
+/// [`Manager::run_schedule`](crate::Manager::run_schedule) does at the end of every run,
+/// so every system sees them once no matter if it runs before or after the sender.
+///
+/// Every event gets an increasing id, which readers use to remember what they already read.
+///
+/// # Examples
+///
+/// ```
+/// use rust_ecs::*;
+/// struct Collision(u32);
+///
+/// let mut events = Events::default();
+/// events.send(Collision(1));
+/// events.update();
+/// events.send(Collision(2));
+/// assert_eq!(2, events.len());
+///
+/// events.update();
+/// assert_eq!(vec![2], events.iter_from(0).map(|e| e.0).collect::<Vec<_>>());
+/// ```
+pub struct Events<E> {
+    previous: Vec<E>,
+    previous_start: usize,
+    current: Vec<E>,
+    current_start: usize,
+}
+
+impl<E> Default for Events<E> {
+    fn default() -> Self {
+        Events {
+            previous: Vec::new(),
+            previous_start: 0,
+            current: Vec::new(),
+            current_start: 0,
+        }
+    }
+}
+
+impl<E> Events<E> {
+    pub fn send(&mut self, event: E) {
+        self.current.push(event);
+    }
+
+    ///
+    /// Drops the events of the previous update and moves the current ones in their place.
+    ///
+    pub fn update(&mut self) {
+        self.previous = std::mem::take(&mut self.current);
+        self.previous_start = self.current_start;
+        self.current_start += self.previous.len();
+    }
+
+    /// Drops every event, without reusing their ids.
+    pub fn clear(&mut self) {
+        self.update();
+        self.update();
+    }
+
+    /// The id the next sent event will get.
+    pub fn next_id(&self) -> usize {
+        self.current_start + self.current.len()
+    }
+
+    /// Amount of events still stored.
+    pub fn len(&self) -> usize {
+        self.previous.len() + self.current.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    ///
+    /// Iterates the stored events with an id of at least `id`, oldest first.
+    ///
+    pub fn iter_from(&self, id: usize) -> impl Iterator<Item = &E> {
+        let previous = id.saturating_sub(self.previous_start).min(self.previous.len());
+        let current = id.saturating_sub(self.current_start).min(self.current.len());
+        self.previous[previous..].iter().chain(self.current[current..].iter())
+    }
+}
+
+///
+/// System parameter sending events of type `E`.
+///
+/// # Panics
+///
+/// Panics when fetched if the event type wasn't added with [`Manager::add_event`](crate::Manager::add_event).
+///
+pub struct EventWriter<'w, E: Send + Sync + 'static> {
+    events: ResMut<'w, Events<E>>,
+}
+
+impl<E: Send + Sync + 'static> EventWriter<'_, E> {
+    pub fn send(&mut self, event: E) {
+        self.events.send(event);
+    }
+}
+
+///
+/// System parameter reading events of type `E`. Every reader remembers the last event
+/// it read, so each event is read once by every system reading it.
+///
+/// # Panics
+///
+/// Panics when fetched if the event type wasn't added with [`Manager::add_event`](crate::Manager::add_event).
+///
+pub struct EventReader<'w, E: Send + Sync + 'static> {
+    events: Res<'w, Events<E>>,
+    next: &'w mut usize,
+}
+
+impl<E: Send + Sync + 'static> EventReader<'_, E> {
+    ///
+    /// Iterates the events that haven't been read by this reader yet, and marks them read.
+    ///
+    pub fn read(&mut self) -> impl Iterator<Item = &E> {
+        let from = *self.next;
+        *self.next = self.events.next_id();
+        self.events.iter_from(from)
+    }
+
+    /// Amount of unread events.
+    pub fn len(&self) -> usize {
+        self.events.iter_from(*self.next).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<E: Send + Sync + 'static> SystemParam for EventWriter<'_, E> {
+    type State = ();
+    type Item<'w> = EventWriter<'w, E>;
+
+    fn access(access: Access) -> Access {
+        access.write_resource::<Events<E>>()
+    }
+
+    fn fetch<'w>(context: &'w SystemContext<'_>, _: &'w mut Self::State) -> Self::Item<'w> {
+        EventWriter {
+            events: context.manager().resource_mut::<Events<E>>().unwrap_or_else(|| missing::<E>()),
+        }
+    }
+}
+
+impl<E: Send + Sync + 'static> SystemParam for EventReader<'_, E> {
+    /// Id of the next unread event.
+    type State = usize;
+    type Item<'w> = EventReader<'w, E>;
+
+    fn access(access: Access) -> Access {
+        access.read_resource::<Events<E>>()
+    }
+
+    fn fetch<'w>(context: &'w SystemContext<'_>, state: &'w mut Self::State) -> Self::Item<'w> {
+        EventReader {
+            events: context.manager().resource::<Events<E>>().unwrap_or_else(|| missing::<E>()),
+            next: state,
+        }
+    }
+}
+
+///
+/// System parameter listing the entities that lost their component `T`, either by removing
+/// the component or by killing the entity. Removals are only logged once a system reading
+/// them was added with [`Manager::add_system`](crate::Manager::add_system).
+///
+/// The log is double buffered like [`Events`]: a removal stays until the second update after
+/// it happened, so it outlives the schedule run it happened in by one run. Every call of
+/// [`Manager::run_schedule`](crate::Manager::run_schedule), [`Manager::run_parallel`](crate::Manager::run_parallel)
+/// and [`Manager::run_task`](crate::Manager::run_task) updates it once. Every reader lists each removal once.
+///
+/// # Examples
+///
+/// ```
+/// use rust_ecs::*;
+/// struct Body(u32);
+///
+/// register_components!(Body);
+///
+/// let manager = Manager::new();
+/// let entity = manager.add_entity();
+/// manager.add_component(&entity, Body(1));
+///
+/// manager.add_system("physics_bridge", move |mut removed: RemovedComponents<Body>| {
+///     assert_eq!(vec![entity], removed.read().collect::<Vec<_>>());
+/// });
+/// manager.kill_entity(entity);
+/// manager.run_schedule().unwrap();
+/// ```
+pub struct RemovedComponents<'w, T> {
+    removed: Vec<Entity>,
+    next: &'w mut usize,
+    end: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Component> RemovedComponents<'_, T> {
+    ///
+    /// Iterates the entities that lost their component since this reader last read, and marks them read.
+    ///
+    pub fn read(&mut self) -> impl Iterator<Item = Entity> + '_ {
+        *self.next = self.end;
+        std::mem::take(&mut self.removed).into_iter()
+    }
+
+    /// Amount of unread removals.
+    pub fn len(&self) -> usize {
+        self.removed.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T: Component> SystemParam for RemovedComponents<'_, T> {
+    /// Id of the next unread removal.
+    type State = usize;
+    type Item<'w> = RemovedComponents<'w, T>;
+
+    fn access(access: Access) -> Access {
+        access.read::<T>()
+    }
+
+    fn init(manager: &Manager) {
+        manager.get_comp_manager().track_removed::<T>();
+    }
+
+    fn fetch<'w>(context: &'w SystemContext<'_>, state: &'w mut Self::State) -> Self::Item<'w> {
+        // The removals are copied out, so the log isn't locked while the system runs.
+        let (removed, end) = match context.components().removed::<T>() {
+            Some(log) => (log.iter_from(*state).copied().collect(), log.next_id()),
+            None => (Vec::new(), *state),
+        };
+        RemovedComponents {
+            removed,
+            next: state,
+            end,
+            _marker: PhantomData,
+        }
+    }
+}
+
+fn missing<E>() -> ! {
+    panic!("Event {} not added to the manager", std::any::type_name::<E>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn double_buffer() {
+        let mut events = Events::default();
+        events.send(0);
+        events.send(1);
+        events.update();
+        events.send(2);
+
+        assert_eq!(events.iter_from(0).copied().collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert_eq!(events.iter_from(1).copied().collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(events.iter_from(3).count(), 0);
+
+        events.update();
+        assert_eq!(events.iter_from(0).copied().collect::<Vec<_>>(), vec![2]);
+        events.update();
+        assert!(events.is_empty());
+        assert_eq!(events.next_id(), 3);
+    }
+
+    #[test]
+    fn clear() {
+        let mut events = Events::default();
+        events.send(0);
+        events.clear();
+        events.send(1);
+
+        assert_eq!(events.iter_from(0).copied().collect::<Vec<_>>(), vec![1]);
+    }
+}
@@ -0,0 +1,395 @@
+use std::ops::Deref;
+use std::collections::HashMap;
+
+use super::cm::View;
+use crate::{AtomicRef, BorrowError, ComponentManager, Entity, Manager};
+
+///
+/// The parent of an entity, maintained by [`Manager::set_parent`].
+///
+#[derive(Clone)]
+pub struct Parent(Entity);
+
+impl Parent {
+    pub fn get(&self) -> Entity {
+        self.0
+    }
+}
+
+///
+/// The children of an entity in the order they were attached, maintained by [`Manager::set_parent`].
+///
+#[derive(Clone)]
+pub struct Children(Vec<Entity>);
+
+impl Deref for Children {
+    type Target = [Entity];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+register_components!(Parent, Children);
+
+///
+/// Depth first iterator over the descendants of an entity, yielding every parent before its children.
+/// See [`Manager::descendants`].
+///
+pub struct Descendants<'a> {
+    // Declared before the manager guard so the set borrow is released first.
+    children: Option<View<'a, Children>>,
+    stack: Vec<Entity>,
+    _guard: Option<AtomicRef<'a, ComponentManager>>,
+}
+
+impl Descendants<'_> {
+    fn push_children(&mut self, entity: &Entity) {
+        if let Some(children) = self.children.as_ref().and_then(|set| set.get(entity)) {
+            self.stack.extend(children.0.iter().rev());
+        }
+    }
+}
+
+impl Iterator for Descendants<'_> {
+    type Item = Entity;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entity = self.stack.pop()?;
+        self.push_children(&entity);
+        Some(entity)
+    }
+}
+
+impl ComponentManager {
+    /// Returns the parent of the entity, if it has one.
+    pub fn parent_of(&self, entity: &Entity) -> Option<Entity> {
+        self.get_components::<Parent>()?.get(entity).map(Parent::get)
+    }
+
+    /// Returns the children of the entity in the order they were attached.
+    pub fn children_of(&self, entity: &Entity) -> Vec<Entity> {
+        self.get_components::<Children>()
+            .and_then(|set| set.get(entity).map(|children| children.0.clone()))
+            .unwrap_or_default()
+    }
+
+    ///
+    /// Iterates the descendants of the entity depth first, see [`Descendants`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the children are borrowed mutably.
+    pub fn descendants(&self, root: &Entity) -> Descendants<'_> {
+        let mut descendants = Descendants {
+            children: self.get_components::<Children>(),
+            stack: Vec::new(),
+            _guard: None,
+        };
+        descendants.push_children(root);
+        descendants
+    }
+
+    pub(crate) fn set_parent(&mut self, child: &Entity, parent: &Entity) {
+        let mut ancestor = Some(*parent);
+        while let Some(entity) = ancestor {
+            assert!(entity != *child, "Entity {:?} can't become a child of its descendant {:?}", child, parent);
+            ancestor = self.parent_of(&entity);
+        }
+
+        self.remove_parent(child);
+        self.add_component(child, Parent(*parent));
+        let attached = self.get_components_mut::<Children>()
+            .and_then(|mut set| set.get_mut(parent).map(|children| children.0.push(*child)))
+            .is_some();
+        if !attached {
+            self.add_component(parent, Children(vec![*child]));
+        }
+    }
+
+    pub(crate) fn remove_parent(&self, child: &Entity) -> Option<Entity> {
+        let parent = self.remove_component::<Parent>(child)?.get();
+        let emptied = self.get_components_mut::<Children>()
+            .and_then(|mut set| set.get_mut(&parent).map(|children| {
+                children.0.retain(|entity| entity != child);
+                children.0.is_empty()
+            }))
+            .unwrap_or(false);
+        if emptied {
+            self.remove_component::<Children>(&parent);
+        }
+        Some(parent)
+    }
+
+    /// Detaches a dying entity from its parent and orphans its children.
+    pub(crate) fn detach(&self, entity: &Entity) {
+        self.remove_parent(entity);
+        for child in self.children_of(entity) {
+            self.remove_component::<Parent>(&child);
+        }
+        self.remove_component::<Children>(entity);
+    }
+
+    ///
+    /// Rewrites the parents and children of the mapped entities after they were moved from another world.
+    /// Links to entities missing from the mapping are dropped.
+    ///
+    pub(crate) fn remap_hierarchy(&self, mapping: &HashMap<Entity, Entity>) {
+        let mut orphans = Vec::new();
+        if let Some(mut parents) = self.get_components_mut::<Parent>() {
+            for entity in mapping.values() {
+                if let Some(parent) = parents.get_mut(entity) {
+                    match mapping.get(&parent.0) {
+                        Some(mapped) => parent.0 = *mapped,
+                        None => orphans.push(*entity),
+                    }
+                }
+            }
+        }
+        let mut childless = Vec::new();
+        if let Some(mut children) = self.get_components_mut::<Children>() {
+            for entity in mapping.values() {
+                if let Some(children) = children.get_mut(entity) {
+                    children.0.retain_mut(|child| mapping.get(child).map(|mapped| *child = *mapped).is_some());
+                    if children.0.is_empty() {
+                        childless.push(*entity);
+                    }
+                }
+            }
+        }
+        for entity in orphans {
+            self.remove_component::<Parent>(&entity);
+        }
+        for entity in childless {
+            self.remove_component::<Children>(&entity);
+        }
+    }
+}
+
+impl Manager {
+    ///
+    /// Makes `child` a child of `parent`, detaching it from its previous parent.
+    /// Killing an entity detaches it from its parent and orphans its children,
+    /// see [`Manager::kill_entity_recursive`] to kill a whole subtree instead.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either entity is dead, if `parent` is `child` or one of its descendants,
+    /// or if the comp_manager is borrowed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    ///
+    /// let manager = Manager::new();
+    ///
+    /// let ship = manager.add_entity();
+    /// let turret = manager.add_entity();
+    /// let barrel = manager.add_entity();
+    /// manager.set_parent(&turret, &ship);
+    /// manager.set_parent(&barrel, &turret);
+    ///
+    /// assert_eq!(Some(ship), manager.parent_of(&turret));
+    /// assert_eq!(vec![turret], manager.children_of(&ship));
+    /// assert_eq!(vec![turret, barrel], manager.descendants(&ship).collect::<Vec<_>>());
+    /// ```
+    pub fn set_parent(&self, child: &Entity, parent: &Entity) {
+        let mut comp_manager = self.get_comp_manager_mut();
+        assert!(self.entity_alive(*child), "Can't attach the dead entity {:?}", child);
+        assert!(self.entity_alive(*parent), "Can't attach {:?} to the dead entity {:?}", child, parent);
+        comp_manager.set_parent(child, parent);
+    }
+
+    ///
+    /// Detaches the entity from its parent and returns the parent, if it had one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the comp_manager is borrowed.
+    pub fn remove_parent(&self, child: &Entity) -> Option<Entity> {
+        self.get_comp_manager_mut().remove_parent(child)
+    }
+
+    ///
+    /// Returns the parent of the entity, if it has one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the comp_manager is borrowed mutably.
+    pub fn parent_of(&self, entity: &Entity) -> Option<Entity> {
+        self.get_comp_manager().parent_of(entity)
+    }
+
+    ///
+    /// Returns the children of the entity in the order they were attached.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the comp_manager is borrowed mutably.
+    pub fn children_of(&self, entity: &Entity) -> Vec<Entity> {
+        self.get_comp_manager().children_of(entity)
+    }
+
+    ///
+    /// Iterates the descendants of the entity depth first, parents before their children.
+    /// The comp_manager stays borrowed while the iterator lives.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the comp_manager is borrowed mutably.
+    pub fn descendants(&self, root: &Entity) -> Descendants<'_> {
+        let guard = self.get_comp_manager();
+        // Safety: the guard is stored in the iterator and outlives the set borrow, see Query::guarded.
+        let comp_manager = unsafe { &*(&*guard as *const ComponentManager) };
+        let mut descendants = comp_manager.descendants(root);
+        descendants._guard = Some(guard);
+        descendants
+    }
+
+    ///
+    /// Kills the entity and all of its descendants, removing all of their components.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the comp_manager is borrowed, see [`Manager::try_kill_entity_recursive`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    ///
+    /// let manager = Manager::new();
+    ///
+    /// let ship = manager.add_entity();
+    /// let turret = manager.add_entity();
+    /// manager.set_parent(&turret, &ship);
+    ///
+    /// manager.kill_entity_recursive(ship);
+    /// assert!(!manager.entity_alive(turret));
+    /// ```
+    pub fn kill_entity_recursive(&self, entity: Entity) {
+        self.try_kill_entity_recursive(entity).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    ///
+    /// Kills the entity and all of its descendants, or returns an error without killing
+    /// anything if the comp_manager is borrowed.
+    ///
+    pub fn try_kill_entity_recursive(&self, entity: Entity) -> Result<(), BorrowError> {
+        let comp_manager = self.comp_manager.try_borrow_mut()?;
+        let mut entities = vec![entity];
+        entities.extend(comp_manager.descendants(&entity));
+        // Killed from the back, so leaves go first and every entity is detached from a parent that is still alive.
+        self.kill_entities(&comp_manager, entities);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(manager: &Manager) -> Vec<Entity> {
+        // 0 -> (1 -> (3, 4), 2 -> 5)
+        let entities: Vec<Entity> = (0..6).map(|_| manager.add_entity()).collect();
+        for (child, parent) in [(1, 0), (2, 0), (3, 1), (4, 1), (5, 2)] {
+            manager.set_parent(&entities[child], &entities[parent]);
+        }
+        entities
+    }
+
+    #[test]
+    fn traversal() {
+        let manager = Manager::new();
+        let e = tree(&manager);
+
+        assert_eq!(manager.descendants(&e[0]).collect::<Vec<_>>(), vec![e[1], e[3], e[4], e[2], e[5]]);
+        assert_eq!(manager.descendants(&e[2]).collect::<Vec<_>>(), vec![e[5]]);
+        assert_eq!(manager.descendants(&e[5]).count(), 0);
+        assert_eq!(manager.children_of(&e[1]), vec![e[3], e[4]]);
+        assert_eq!(manager.parent_of(&e[0]), None);
+    }
+
+    #[test]
+    fn reparent() {
+        let manager = Manager::new();
+        let e = tree(&manager);
+
+        manager.set_parent(&e[1], &e[5]);
+        assert_eq!(manager.children_of(&e[0]), vec![e[2]]);
+        assert_eq!(manager.descendants(&e[0]).collect::<Vec<_>>(), vec![e[2], e[5], e[1], e[3], e[4]]);
+
+        assert_eq!(manager.remove_parent(&e[5]), Some(e[2]));
+        assert!(!manager.get_comp_manager().has_component::<Children>(&e[2]));
+        assert_eq!(manager.remove_parent(&e[5]), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn parent_cycle() {
+        let manager = Manager::new();
+        let e = tree(&manager);
+        manager.set_parent(&e[0], &e[4]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn dead_parent() {
+        let manager = Manager::new();
+        let e = tree(&manager);
+        manager.kill_entity(e[5]);
+        manager.set_parent(&e[3], &e[5]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn dead_child() {
+        let manager = Manager::new();
+        let e = tree(&manager);
+        manager.kill_entity(e[5]);
+        manager.set_parent(&e[5], &e[3]);
+    }
+
+    #[test]
+    fn remap_drops_unmapped() {
+        let manager = Manager::new();
+        let e = tree(&manager);
+        let mapping: HashMap<Entity, Entity> = [(e[1], e[1]), (e[3], e[3])].iter().copied().collect();
+        manager.get_comp_manager().remap_hierarchy(&mapping);
+
+        assert_eq!(manager.parent_of(&e[1]), None);
+        assert_eq!(manager.parent_of(&e[3]), Some(e[1]));
+        assert_eq!(manager.children_of(&e[1]), vec![e[3]]);
+
+        let mapping: HashMap<Entity, Entity> = [(e[2], e[2])].iter().copied().collect();
+        manager.get_comp_manager().remap_hierarchy(&mapping);
+        assert!(!manager.get_comp_manager().has_component::<Children>(&e[2]));
+    }
+
+    #[test]
+    fn kill_detaches() {
+        let manager = Manager::new();
+        let e = tree(&manager);
+
+        manager.kill_entity(e[1]);
+        assert_eq!(manager.children_of(&e[0]), vec![e[2]]);
+        assert_eq!(manager.parent_of(&e[3]), None);
+        assert_eq!(manager.parent_of(&e[4]), None);
+    }
+
+    #[test]
+    fn kill_recursive() {
+        let manager = Manager::new();
+        let e = tree(&manager);
+
+        manager.kill_entity_recursive(e[1]);
+        assert_eq!(e.iter().map(|e| manager.entity_alive(*e)).collect::<Vec<_>>(), vec![true, false, true, false, false, true]);
+        assert_eq!(manager.descendants(&e[0]).collect::<Vec<_>>(), vec![e[2], e[5]]);
+
+        manager.kill_entity_recursive(e[0]);
+        assert!(e.iter().all(|e| !manager.entity_alive(*e)));
+        assert_eq!(manager.query::<(&Parent,)>().iter().count(), 0);
+        assert_eq!(manager.query::<(&Children,)>().iter().count(), 0);
+    }
+}
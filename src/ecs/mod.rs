@@ -7,25 +7,58 @@ pub mod systems;
 #[macro_use]
 mod cm;
 mod entity_handler;
+pub mod query;
+mod commands;
+mod cell;
+mod resources;
+mod events;
+mod bundle;
+mod hierarchy;
+mod relations;
+mod snapshot;
+mod save;
+mod scene;
+mod transfer;
+#[cfg(feature = "serde")]
+mod serialize;
 
 pub use cm::ComponentManager;
-pub use entity_handler::Entity;
+pub use entity_handler::{Entity, EntityId, VersionOverflow, MAX_VERSION};
+pub use query::{Query, With, Without, Added, Changed, Or};
+pub use commands::Commands;
+pub use cell::{AtomicRef, AtomicRefMut, BorrowError};
+pub use resources::{Res, ResMut};
+pub use events::{Events, EventReader, EventWriter, RemovedComponents};
+pub use bundle::{Bundle, EntityBuilder};
+pub use hierarchy::{Parent, Children, Descendants};
+pub use relations::{Relation, Relations, OnTargetKilled};
+pub use snapshot::Snapshot;
+pub use save::{LoadError, FORMAT_VERSION};
+pub use scene::{Fields, SceneError, Value};
+#[cfg(feature = "serde")]
+pub use serialize::WorldSerializer;
+pub use systems::{Access, Stage, ScheduleError, SystemConfig, SystemParam, SystemFunction};
 
-use std::cell::Ref;
-use std::cell::RefMut;
-use std::cell::RefCell;
+use std::sync::Mutex;
+use std::sync::RwLock;
+use cell::AtomicRefCell;
 
-pub(crate) type ComponentView<'l> = Ref<'l, ComponentManager>;
-
-// use std::sync::RwLock;
+pub(crate) type ComponentView<'l> = AtomicRef<'l, ComponentManager>;
 
 ///
 /// Manager data type. Used to run the ecs.
 /// 
+/// The manager is `Send` and `Sync`, so it can be shared between threads. Components are
+/// borrowed through atomic borrow flags that never block. Conflicting borrows panic in the
+/// plain methods and return a [`BorrowError`] in their `try_` variants.
+/// 
 pub struct Manager {
-    ent_handler: RefCell<entity_handler::EntityHandler>,
-    schedule: RefCell<std::collections::HashMap<String, systems::System>>,
-    comp_manager: RefCell<ComponentManager>,
+    ent_handler: Mutex<entity_handler::EntityHandler>,
+    schedule: RwLock<systems::Schedule>,
+    comp_manager: AtomicRefCell<ComponentManager>,
+    resources: AtomicRefCell<resources::Resources>,
+    event_updates: RwLock<Vec<fn(&Manager)>>,
+    prefabs: RwLock<scene::Prefabs>,
 }
 
 ///
@@ -65,8 +98,42 @@ pub struct Manager {
 /// group_partial!(CompA => CompB);
 /// ```
 /// 
-pub trait Component: 'static {
+/// ## Implement by hand for a component that is never grouped.
+/// ```
+/// use rust_ecs::*;
+/// 
+/// struct CompA {}
+/// 
+/// impl Component for CompA {
+///     fn group(_: &ComponentManager, _: &Entity) { }
+/// }
+/// ```
+/// 
+pub trait Component: 'static + Send + Sync {
     fn group(cm: &ComponentManager, entity: &Entity);
+
+    /// Groups every entity of a batch. The group macros borrow each set once for the whole batch.
+    fn group_batch(cm: &ComponentManager, entities: &[Entity]) {
+        for entity in entities {
+            Self::group(cm, entity);
+        }
+    }
+
+    /// Ungroups the entity from this component's set if `removed` is a member of its group.
+    /// Does nothing by default, for components that are never grouped.
+    fn ungroup(_cm: &ComponentManager, _entity: &Entity, _removed: std::any::TypeId) { }
+
+    /// Returns true if `types` are exactly the components of this component's full group.
+    /// Returns false by default, for components that are never fully grouped.
+    fn grouped_by(_types: &[std::any::TypeId]) -> bool {
+        false
+    }
+
+    /// Returns every component of this component's full group, itself included.
+    /// Empty by default, for components that are never fully grouped.
+    fn group_members() -> Vec<std::any::TypeId> {
+        Vec::new()
+    }
 }
 
 impl Manager {
@@ -80,20 +147,34 @@ impl Manager {
     /// let manager = rust_ecs::Manager::new();
     /// ```
     pub fn new() -> Self {
+        Self::with_version_overflow(VersionOverflow::default())
+    }
+
+    ///
+    /// Creates a new manager with the given policy for entities killed at [`MAX_VERSION`].
+    /// [`Manager::new`] retires such slots.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    /// 
+    /// let manager = Manager::with_version_overflow(VersionOverflow::Wrap);
+    /// ```
+    pub fn with_version_overflow(overflow: VersionOverflow) -> Self {
         Manager {
-            ent_handler: RefCell::new(entity_handler::EntityHandler::new()),
-            schedule: RefCell::new(std::collections::HashMap::new()),
-            comp_manager: RefCell::new(ComponentManager::new()),
+            ent_handler: Mutex::new(entity_handler::EntityHandler::with_overflow(overflow)),
+            schedule: RwLock::new(systems::Schedule::default()),
+            comp_manager: AtomicRefCell::new(ComponentManager::new()),
+            resources: AtomicRefCell::new(resources::Resources::default()),
+            event_updates: RwLock::new(Vec::new()),
+            prefabs: RwLock::new(scene::Prefabs::default()),
         }
     }
 
     ///
     /// Registers a new entity and return its identifier.
-    /// 
-    /// # Panics
-    /// 
-    /// Panics if any other thread adds, kills or check a entity alive status currently.
-    /// Will be changed soon when Manager becomes threadsafe.
+    /// Blocks while another thread adds, kills or checks an entity.
     ///
     /// # Examples
     ///
@@ -103,16 +184,83 @@ impl Manager {
     /// let entity = manager.add_entity();
     /// ```
     pub fn add_entity(&self) -> Entity {
-        self.ent_handler.borrow_mut().new_entity()
+        self.ent_handler.lock().unwrap().new_entity()
+    }
+
+    ///
+    /// Registers a new entity and returns a builder adding components to it.
+    /// See [`EntityBuilder`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    /// struct Pos(i32);
+    /// struct Vel(i32);
+    /// 
+    /// register_components!(Pos, Vel);
+    /// 
+    /// let manager = Manager::new();
+    /// 
+    /// let entity = manager.spawn().with(Pos(0)).with(Vel(2)).id();
+    /// ```
+    pub fn spawn(&self) -> EntityBuilder<'_> {
+        EntityBuilder::new(self, self.add_entity())
+    }
+
+    ///
+    /// Spawns an entity for every bundle and returns them in order. Storage for the entities
+    /// and their components is reserved up front, every component set is filled in one pass
+    /// and the entities are grouped afterwards. Hooks fire type by type rather than entity by entity.
+    /// 
+    /// # Panics
+    /// 
+    /// Panics if the comp_manager is borrowed, see [`Manager::try_spawn_batch`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    /// struct Pos(i32);
+    /// struct Vel(i32);
+    /// 
+    /// register_components!(Pos, Vel);
+    /// 
+    /// let manager = Manager::new();
+    /// 
+    /// let entities = manager.spawn_batch((0..100).map(|i| (Pos(i), Vel(1))));
+    /// assert_eq!(100, entities.len());
+    /// ```
+    pub fn spawn_batch<B: Bundle, I: IntoIterator<Item = B>>(&self, bundles: I) -> Vec<Entity> {
+        self.try_spawn_batch(bundles).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    ///
+    /// Spawns an entity for every bundle, or returns an error without spawning anything
+    /// if the comp_manager is borrowed.
+    ///
+    pub fn try_spawn_batch<B: Bundle, I: IntoIterator<Item = B>>(&self, bundles: I) -> Result<Vec<Entity>, BorrowError> {
+        let mut comp_manager = self.comp_manager.try_borrow_mut()?;
+        let bundles: Vec<B> = bundles.into_iter().collect();
+        let entities: Vec<Entity> = {
+            let mut ent_handler = self.ent_handler.lock().unwrap();
+            ent_handler.reserve(bundles.len());
+            (0..bundles.len()).map(|_| ent_handler.new_entity()).collect()
+        };
+
+        B::insert_batch(bundles, &mut comp_manager, &entities);
+        B::group_batch(&comp_manager, &entities);
+        Ok(entities)
     }
 
     ///
     /// Kills a entity. Will remove all components belonging to the entity.
+    /// The entity is detached from its parent and its children are orphaned, see [`Manager::set_parent`].
+    /// Its relations are removed, and relations pointing at it follow their [`OnTargetKilled`] policy.
     /// 
     /// # Panics
     /// 
-    /// Panics if any other thread adds, kills or check a entity alive status currently.
-    /// Will be changed soon when Manager becomes threadsafe.
+    /// Panics if the comp_manager is borrowed, see [`Manager::try_kill_entity`].
     ///
     /// # Examples
     ///
@@ -124,17 +272,48 @@ impl Manager {
     /// manager.kill_entity(entity);
     /// ```
 	pub fn kill_entity(&self, entity: Entity) {
-        self.ent_handler.borrow_mut().kill_entity(entity);
-        self.comp_manager.borrow().clean_components(entity);
+        self.try_kill_entity(entity).unwrap_or_else(|e| panic!("{}", e))
     }
 
     ///
-    /// Check if a entity is currently alive.
+    /// Kills a entity, or returns an error without killing it if the comp_manager is borrowed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let manager = rust_ecs::Manager::new();
     /// 
-    /// # Panics
+    /// let entity = manager.add_entity();
+    /// 
+    /// let comp_manager = manager.get_comp_manager();
+    /// assert!(manager.try_kill_entity(entity).is_err());
+    /// drop(comp_manager);
     /// 
-    /// Panics if any other thread adds, kills or check a entity alive status currently.
-    /// Will be changed soon when Manager becomes threadsafe.
+    /// assert!(manager.try_kill_entity(entity).is_ok());
+    /// assert!(!manager.entity_alive(entity));
+    /// ```
+    pub fn try_kill_entity(&self, entity: Entity) -> Result<(), BorrowError> {
+        let comp_manager = self.comp_manager.try_borrow_mut()?;
+        self.kill_entities(&comp_manager, vec![entity]);
+        Ok(())
+    }
+
+    ///
+    /// Kills the entities from last to first, followed by every source of a relation that kills
+    /// its sources with the target.
+    ///
+    fn kill_entities(&self, comp_manager: &ComponentManager, mut entities: Vec<Entity>) {
+        while let Some(entity) = entities.pop() {
+            self.ent_handler.lock().unwrap().kill_entity(entity);
+            comp_manager.detach(&entity);
+            comp_manager.clean_components(entity);
+            entities.extend(comp_manager.clean_relations(entity));
+        }
+    }
+
+    ///
+    /// Check if a entity is currently alive.
+    /// Blocks while another thread adds, kills or checks an entity.
     ///
     /// # Examples
     ///
@@ -147,16 +326,15 @@ impl Manager {
     /// assert!(!manager.entity_alive(entity));
     /// ```
     pub fn entity_alive(&self, entity: Entity) -> bool {
-        self.ent_handler.borrow().is_alive(entity)
+        self.ent_handler.lock().unwrap().is_alive(entity)
     }
 
     ///
-    /// Adds a new component to an entity.
+    /// Adds a new component to an entity, replacing the component of the same type if it has one.
     /// 
     /// # Panics
     /// 
-    /// Panics if any other thread adds a component or borrows the comp_manager currently.
-    /// Will be changed soon when Manager becomes threadsafe.
+    /// Panics if the comp_manager is borrowed, see [`Manager::try_add_component`].
     ///
     /// # Examples
     ///
@@ -172,7 +350,270 @@ impl Manager {
     /// manager.add_component(&entity, Comp {});
     /// ```
     pub fn add_component<T: Component >(&self, entity: &Entity, component: T) {
-        self.comp_manager.borrow_mut().add_component(entity, component);
+        self.try_add_component(entity, component).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    ///
+    /// Adds a new component to an entity, or returns an error if the comp_manager is borrowed.
+    /// The component is dropped in that case.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    /// struct Comp {}
+    /// 
+    /// register_components!(Comp);
+    /// 
+    /// let manager = Manager::new();
+    /// 
+    /// let entity = manager.add_entity();
+    /// let query = manager.query::<(&Comp,)>();
+    /// assert!(manager.try_add_component(&entity, Comp {}).is_err());
+    /// ```
+    pub fn try_add_component<T: Component>(&self, entity: &Entity, component: T) -> Result<(), BorrowError> {
+        self.comp_manager.try_borrow_mut()?.add_component(entity, component);
+        Ok(())
+    }
+
+    ///
+    /// Adds every component of the bundle to an entity in one operation, grouping it once at the end.
+    /// See [`Bundle`].
+    /// 
+    /// # Panics
+    /// 
+    /// Panics if the comp_manager is borrowed, see [`Manager::try_add_bundle`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    /// struct Pos(i32);
+    /// struct Vel(i32);
+    /// 
+    /// register_components!(Pos, Vel);
+    /// 
+    /// let manager = Manager::new();
+    /// 
+    /// let entity = manager.add_entity();
+    /// manager.add_bundle(&entity, (Pos(0), Vel(2)));
+    /// ```
+    pub fn add_bundle<B: Bundle>(&self, entity: &Entity, bundle: B) {
+        self.try_add_bundle(entity, bundle).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    ///
+    /// Adds every component of the bundle to an entity, or returns an error if the comp_manager is borrowed.
+    /// The bundle is dropped in that case.
+    ///
+    pub fn try_add_bundle<B: Bundle>(&self, entity: &Entity, bundle: B) -> Result<(), BorrowError> {
+        self.comp_manager.try_borrow_mut()?.add_bundle(entity, bundle);
+        Ok(())
+    }
+
+    ///
+    /// Registers a callback fired after a component of type `T` is added to an entity.
+    /// See [`ComponentManager::on_add`].
+    /// 
+    /// # Panics
+    /// 
+    /// Panics if the comp_manager is borrowed currently.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    /// use std::sync::{Arc, Mutex};
+    /// struct Body(u32);
+    /// 
+    /// register_components!(Body);
+    /// 
+    /// let manager = Manager::new();
+    /// let bodies = Arc::new(Mutex::new(Vec::new()));
+    /// 
+    /// let added = bodies.clone();
+    /// manager.on_add::<Body, _>(move |entity, _| added.lock().unwrap().push(entity));
+    /// let removed = bodies.clone();
+    /// manager.on_remove::<Body, _>(move |entity, _| removed.lock().unwrap().retain(|e| *e != entity));
+    /// 
+    /// let entity = manager.add_entity();
+    /// manager.add_component(&entity, Body(1));
+    /// assert_eq!(vec![entity], *bodies.lock().unwrap());
+    /// 
+    /// manager.kill_entity(entity);
+    /// assert!(bodies.lock().unwrap().is_empty());
+    /// ```
+    pub fn on_add<T: Component, F: Fn(Entity, &T) + Send + Sync + 'static>(&self, hook: F) {
+        self.comp_manager.borrow_mut().on_add(hook);
+    }
+
+    ///
+    /// Registers a callback fired after a component of type `T` is overwritten.
+    /// See [`ComponentManager::on_replace`].
+    /// 
+    /// # Panics
+    /// 
+    /// Panics if the comp_manager is borrowed currently.
+    ///
+    pub fn on_replace<T: Component, F: Fn(Entity, &T, &T) + Send + Sync + 'static>(&self, hook: F) {
+        self.comp_manager.borrow_mut().on_replace(hook);
+    }
+
+    ///
+    /// Registers a callback fired after a component of type `T` is removed or its entity killed.
+    /// See [`ComponentManager::on_remove`].
+    /// 
+    /// # Panics
+    /// 
+    /// Panics if the comp_manager is borrowed currently.
+    ///
+    pub fn on_remove<T: Component, F: Fn(Entity, &T) + Send + Sync + 'static>(&self, hook: F) {
+        self.comp_manager.borrow_mut().on_remove(hook);
+    }
+
+    ///
+    /// Removes a component from an entity and returns it, or None if the entity
+    /// did not have the component. Grouped components are ungrouped from every set in their group.
+    /// 
+    /// # Panics
+    /// 
+    /// Panics if the comp_manager is borrowed, see [`Manager::try_remove_component`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    /// struct Pos(i32);
+    /// struct Vel(i32);
+    /// 
+    /// group!(Pos, Vel);
+    /// 
+    /// let manager = Manager::new();
+    /// 
+    /// let entity = manager.add_entity();
+    /// manager.add_component(&entity, Pos(1));
+    /// manager.add_component(&entity, Vel(2));
+    /// 
+    /// assert_eq!(2, manager.remove_component::<Vel>(&entity).unwrap().0);
+    /// assert!(manager.remove_component::<Vel>(&entity).is_none());
+    /// ```
+    pub fn remove_component<T: Component>(&self, entity: &Entity) -> Option<T> {
+        self.try_remove_component::<T>(entity).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    ///
+    /// Removes a component from an entity and returns it, or returns an error without
+    /// removing anything if the comp_manager is borrowed.
+    ///
+    pub fn try_remove_component<T: Component>(&self, entity: &Entity) -> Result<Option<T>, BorrowError> {
+        Ok(self.comp_manager.try_borrow_mut()?.remove_component::<T>(entity))
+    }
+
+    ///
+    /// Queries all entities having every component in `Q`. See [`ComponentManager::query`].
+    /// 
+    /// # Panics
+    /// 
+    /// Panics if the comp_manager or any of the queried components are mutably borrowed,
+    /// or if the same component is queried both mutably and immutably. See [`Manager::try_query`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    /// struct Pos(i32);
+    /// struct Vel(i32);
+    /// 
+    /// register_components!(Pos, Vel);
+    /// 
+    /// let manager = Manager::new();
+    /// 
+    /// let entity = manager.add_entity();
+    /// manager.add_component(&entity, Pos(0));
+    /// manager.add_component(&entity, Vel(2));
+    /// 
+    /// for (e, pos, vel) in &mut manager.query::<(&mut Pos, &Vel)>() {
+    ///     assert_eq!(e, entity);
+    ///     pos.0 += vel.0;
+    /// }
+    /// ```
+    pub fn query<Q: query::QueryData>(&self) -> Query<'_, Q> {
+        self.try_query::<Q>().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    ///
+    /// Queries all entities having every component in `Q`, or returns an error if the
+    /// comp_manager or any of the queried components can't be borrowed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    /// struct Pos(i32);
+    /// 
+    /// register_components!(Pos);
+    /// 
+    /// let manager = Manager::new();
+    /// 
+    /// let entity = manager.add_entity();
+    /// manager.add_component(&entity, Pos(0));
+    /// 
+    /// let query = manager.query::<(&mut Pos,)>();
+    /// assert_eq!(
+    ///     manager.try_query::<(&Pos,)>().err(),
+    ///     Some(BorrowError::MutablyBorrowed(std::any::type_name::<sparse_set::SparseSet<Entity, Pos>>())),
+    /// );
+    /// ```
+    pub fn try_query<Q: query::QueryData>(&self) -> Result<Query<'_, Q>, BorrowError> {
+        let guard = self.comp_manager.try_borrow()?;
+        // Safety: the guard is stored in the query and outlives the reference,
+        // so the comp_manager can't be mutably borrowed while the reference is in use.
+        let comp_manager = unsafe { &*(&*guard as *const ComponentManager) };
+        Query::guarded(guard, comp_manager)
+    }
+
+    ///
+    /// Queries all entities having every component in `Q` and matching the filter `F`.
+    /// See [`ComponentManager::query_filtered`].
+    /// 
+    /// # Panics
+    /// 
+    /// Panics if the comp_manager or any of the queried components are mutably borrowed,
+    /// or if the same component is queried or filtered both mutably and immutably.
+    /// See [`Manager::try_query_filtered`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    /// struct Pos(i32);
+    /// struct Vel(i32);
+    /// struct Frozen;
+    /// 
+    /// register_components!(Pos, Vel, Frozen);
+    /// 
+    /// let manager = Manager::new();
+    /// 
+    /// let entity = manager.add_entity();
+    /// manager.add_component(&entity, Pos(0));
+    /// manager.add_component(&entity, Vel(2));
+    /// manager.add_component(&entity, Frozen);
+    /// 
+    /// assert_eq!(0, manager.query_filtered::<(&mut Pos, &Vel), Without<Frozen>>().iter().count());
+    /// ```
+    pub fn query_filtered<Q: query::QueryData, F: query::QueryFilter>(&self) -> Query<'_, Q, F> {
+        self.try_query_filtered::<Q, F>().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    ///
+    /// Queries all entities having every component in `Q` and matching the filter `F`,
+    /// or returns an error if the comp_manager or any of the components can't be borrowed.
+    ///
+    pub fn try_query_filtered<Q: query::QueryData, F: query::QueryFilter>(&self) -> Result<Query<'_, Q, F>, BorrowError> {
+        let guard = self.comp_manager.try_borrow()?;
+        // Safety: see try_query.
+        let comp_manager = unsafe { &*(&*guard as *const ComponentManager) };
+        Query::guarded(guard, comp_manager)
     }
 
     ///
@@ -181,34 +622,391 @@ impl Manager {
     /// # Panics
     /// 
     /// Panics if the value is currently mutably borrowed.
-    /// Will be changed soon when Manager becomes threadsafe.
     ///
-    pub fn get_comp_manager(&self) -> Ref<'_, ComponentManager> {
+    pub fn get_comp_manager(&self) -> AtomicRef<'_, ComponentManager> {
         self.comp_manager.borrow()
     }
 
+    ///
+    /// Borrows the component sub manager, or returns an error if it is mutably borrowed.
+    ///
+    pub fn try_get_comp_manager(&self) -> Result<AtomicRef<'_, ComponentManager>, BorrowError> {
+        self.comp_manager.try_borrow()
+    }
+
     ///
     /// Borrows the component sub manager mutably.
     /// 
     /// # Panics
     /// 
     /// Panics if the value is currently borrowed.
-    /// Will be changed soon when Manager becomes threadsafe.
     ///
-    pub fn get_comp_manager_mut(&self) -> RefMut<'_, ComponentManager> {
+    pub fn get_comp_manager_mut(&self) -> AtomicRefMut<'_, ComponentManager> {
         self.comp_manager.borrow_mut()
     }
 
-    pub fn register_task<F: 'static + Fn(ComponentView)>(&self, name: &str, func: F) {
-        self.schedule.borrow_mut().insert(String::from(name), systems::System::new(func));
+    ///
+    /// Borrows the component sub manager mutably, or returns an error if it is borrowed.
+    ///
+    pub fn try_get_comp_manager_mut(&self) -> Result<AtomicRefMut<'_, ComponentManager>, BorrowError> {
+        self.comp_manager.try_borrow_mut()
+    }
+
+    ///
+    /// Inserts a resource, a value that exists once per manager. Returns the resource
+    /// of the same type it replaces, if any.
+    /// 
+    /// # Panics
+    /// 
+    /// Panics if any resource is borrowed currently.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    /// struct Clock(u32);
+    /// 
+    /// let manager = Manager::new();
+    /// 
+    /// manager.insert_resource(Clock(0));
+    /// manager.resource_mut::<Clock>().unwrap().0 += 1;
+    /// 
+    /// assert_eq!(1, manager.resource::<Clock>().unwrap().0);
+    /// assert_eq!(1, manager.remove_resource::<Clock>().unwrap().0);
+    /// assert!(manager.resource::<Clock>().is_none());
+    /// ```
+    pub fn insert_resource<T: Send + Sync + 'static>(&self, resource: T) -> Option<T> {
+        self.resources.borrow_mut().insert(resource)
+    }
+
+    ///
+    /// Removes a resource and returns it, or None if there was no resource of the type.
+    /// 
+    /// # Panics
+    /// 
+    /// Panics if any resource is borrowed currently.
+    ///
+    pub fn remove_resource<T: Send + Sync + 'static>(&self) -> Option<T> {
+        self.resources.borrow_mut().remove::<T>()
+    }
+
+    /// Returns true if there is a resource of the type.
+    pub fn has_resource<T: Send + Sync + 'static>(&self) -> bool {
+        self.resources.borrow().contains::<T>()
+    }
+
+    ///
+    /// Borrows a resource, or returns None if there is no resource of the type.
+    /// 
+    /// # Panics
+    /// 
+    /// Panics if the resource is mutably borrowed, see [`Manager::try_resource`].
+    ///
+    pub fn resource<T: Send + Sync + 'static>(&self) -> Option<Res<'_, T>> {
+        self.try_resource::<T>().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    ///
+    /// Borrows a resource, or returns an error if it is mutably borrowed.
+    /// 
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    /// struct Clock(u32);
+    /// 
+    /// let manager = Manager::new();
+    /// manager.insert_resource(Clock(0));
+    /// 
+    /// let clock = manager.resource_mut::<Clock>();
+    /// assert!(manager.try_resource::<Clock>().is_err());
+    /// ```
+    pub fn try_resource<T: Send + Sync + 'static>(&self) -> Result<Option<Res<'_, T>>, BorrowError> {
+        Res::borrow(self.resources.try_borrow()?)
+    }
+
+    ///
+    /// Borrows a resource mutably, or returns None if there is no resource of the type.
+    /// 
+    /// # Panics
+    /// 
+    /// Panics if the resource is borrowed, see [`Manager::try_resource_mut`].
+    ///
+    pub fn resource_mut<T: Send + Sync + 'static>(&self) -> Option<ResMut<'_, T>> {
+        self.try_resource_mut::<T>().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    ///
+    /// Borrows a resource mutably, or returns an error if it is borrowed.
+    ///
+    pub fn try_resource_mut<T: Send + Sync + 'static>(&self) -> Result<Option<ResMut<'_, T>>, BorrowError> {
+        ResMut::borrow(self.resources.try_borrow()?)
+    }
+
+    ///
+    /// Adds an [`Events`] channel for events of type `E`, which systems use through
+    /// [`EventWriter`] and [`EventReader`]. The events are updated at the end of every
+    /// [`Manager::run_schedule`], so they live for two runs. Adding the same type twice does nothing.
+    /// 
+    /// # Panics
+    /// 
+    /// Panics if any resource is borrowed currently.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    /// struct Collision(u32);
+    /// 
+    /// let manager = Manager::new();
+    /// manager.add_event::<Collision>();
+    /// 
+    /// manager.add_system("physics", |mut collisions: EventWriter<Collision>| {
+    ///     collisions.send(Collision(1));
+    /// });
+    /// manager.add_system("sound", |mut collisions: EventReader<Collision>| {
+    ///     for collision in collisions.read() {
+    ///         assert_eq!(1, collision.0);
+    ///     }
+    /// }).after("physics");
+    /// manager.run_schedule().unwrap();
+    /// ```
+    pub fn add_event<E: Send + Sync + 'static>(&self) {
+        if self.has_resource::<Events<E>>() {
+            return;
+        }
+        self.insert_resource(Events::<E>::default());
+        self.event_updates.write().unwrap().push(|manager| {
+            if let Some(mut events) = manager.resource_mut::<Events<E>>() {
+                events.update();
+            }
+        });
+    }
+
+    ///
+    /// Registers a system under the given name. The system gets the component sub manager
+    /// and a [`Commands`] buffer for structural changes, which is applied when the system returns.
+    /// The system doesn't declare its components, so it never runs in parallel with other systems.
+    /// See [`Manager::register_system`].
+    /// 
+    /// The returned [`SystemConfig`] places the system in the schedule run by [`Manager::run_schedule`].
+    /// Registering a system under a name already in use replaces the system and its placement.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    /// struct Bullet;
+    /// 
+    /// register_components!(Bullet);
+    /// 
+    /// let manager = Manager::new();
+    /// 
+    /// manager.register_task("spawn", |_, commands| {
+    ///     let entity = commands.spawn();
+    ///     commands.add_component(&entity, Bullet);
+    /// });
+    /// manager.run_task("spawn");
+    /// 
+    /// assert_eq!(1, manager.get_comp_manager().get_components::<Bullet>().unwrap().len());
+    /// ```
+    pub fn register_task<F: 'static + Fn(ComponentView, &mut Commands) + Send + Sync>(&self, name: &str, func: F) -> SystemConfig<'_> {
+        self.schedule.write().unwrap().insert(name, systems::System::new(func));
+        SystemConfig::new(self, name)
+    }
+
+    ///
+    /// Registers a system under the given name, declaring the components it reads and writes.
+    /// [`Manager::run_parallel`] runs it at the same time as systems it doesn't conflict with.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    /// struct Pos(i32);
+    /// struct Vel(i32);
+    /// 
+    /// register_components!(Pos, Vel);
+    /// 
+    /// let manager = Manager::new();
+    /// 
+    /// manager.register_system("movement", Access::new().read::<Vel>().write::<Pos>(), |cm, _| {
+    ///     for (_, pos, vel) in &mut cm.query::<(&mut Pos, &Vel)>() {
+    ///         pos.0 += vel.0;
+    ///     }
+    /// });
+    /// ```
+    pub fn register_system<F: 'static + Fn(ComponentView, &mut Commands) + Send + Sync>(&self, name: &str, access: Access, func: F) -> SystemConfig<'_> {
+        self.schedule.write().unwrap().insert(name, systems::System::with_access(access, func));
+        SystemConfig::new(self, name)
+    }
+
+    ///
+    /// Registers a function as a system under the given name. Every argument of the function is a
+    /// [`SystemParam`], like a [`Query`], [`Res`] or [`Commands`], which is borrowed right before the system runs.
+    /// The components the system reads and writes are taken from the parameters,
+    /// so [`Manager::run_parallel`] and [`Manager::run_schedule`] can run it in parallel.
+    ///
+    /// # Panics
+    /// 
+    /// The system panics when run if two of its parameters borrow the same component
+    /// and one of them does it mutably. Adding a system reading [`RemovedComponents`]
+    /// panics if the comp_manager is borrowed mutably.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    /// struct Pos(i32);
+    /// struct Vel(i32);
+    /// 
+    /// register_components!(Pos, Vel);
+    /// 
+    /// fn movement(mut query: Query<(&mut Pos, &Vel)>) {
+    ///     for (_, pos, vel) in query.iter() {
+    ///         pos.0 += vel.0;
+    ///     }
+    /// }
+    /// 
+    /// let manager = Manager::new();
+    /// 
+    /// manager.add_system("movement", movement);
+    /// manager.add_system("spawn", |mut commands: Commands| {
+    ///     let entity = commands.spawn();
+    ///     commands.add_component(&entity, Pos(0));
+    ///     commands.add_component(&entity, Vel(2));
+    /// }).before("movement");
+    /// manager.run_schedule().unwrap();
+    /// 
+    /// // The commands of spawn are applied before movement runs.
+    /// let pos = manager.query::<(&Pos,)>().iter().map(|(_, pos)| pos.0).collect::<Vec<_>>();
+    /// assert_eq!(pos, vec![2]);
+    /// ```
+    pub fn add_system<Params, F: SystemFunction<Params>>(&self, name: &str, func: F) -> SystemConfig<'_> {
+        F::init(self);
+        self.schedule.write().unwrap().insert(name, systems::System::from_function(func));
+        SystemConfig::new(self, name)
     }
 
+    ///
+    /// Runs the system registered under the given name and applies its commands,
+    /// then updates the removal logs, see [`RemovedComponents`].
+    ///
+    /// # Panics
+    /// 
+    /// Panics if the comp_manager is borrowed mutably currently.
+    ///
     pub fn run_task(&self, name: &str) {
-        let n = String::from(name);
-        match self.schedule.borrow().get(&n) {
-            Some(task) => task.run(self.comp_manager.borrow()),
-            None => print!("No task found with ID {}\n", name),
+        let task = self.schedule.read().unwrap().get(name);
+        match task {
+            Some(task) => {
+                let mut commands = Commands::new(self);
+                task.run(self.comp_manager.borrow(), &mut commands);
+                commands.apply();
+                self.get_comp_manager().update_removed();
+            },
+            None => println!("No task found with ID {}", name),
+        }
+    }
+
+    ///
+    /// Runs the named systems on a pool of worker threads. The systems are split into batches
+    /// of systems that don't conflict with each other, which run one batch after the other.
+    /// Conflicting systems run in the order they are given in. The commands of a batch are
+    /// applied in that order as well, once every system of the batch has returned.
+    /// Names without a registered system are skipped. The removal logs are updated
+    /// once all systems are done, see [`RemovedComponents`].
+    ///
+    /// # Panics
+    /// 
+    /// Panics if any of the systems panics, or if the comp_manager is borrowed mutably currently.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    /// struct Pos(i32);
+    /// struct Vel(i32);
+    /// struct Hp(i32);
+    /// 
+    /// register_components!(Pos, Vel, Hp);
+    /// 
+    /// let manager = Manager::new();
+    /// let entity = manager.add_entity();
+    /// manager.add_component(&entity, Pos(0));
+    /// manager.add_component(&entity, Vel(2));
+    /// manager.add_component(&entity, Hp(10));
+    /// 
+    /// manager.register_system("movement", Access::new().read::<Vel>().write::<Pos>(), |cm, _| {
+    ///     for (_, pos, vel) in &mut cm.query::<(&mut Pos, &Vel)>() {
+    ///         pos.0 += vel.0;
+    ///     }
+    /// });
+    /// manager.register_system("poison", Access::new().write::<Hp>(), |cm, _| {
+    ///     for (_, hp) in &mut cm.query::<(&mut Hp,)>() {
+    ///         hp.0 -= 1;
+    ///     }
+    /// });
+    /// manager.run_parallel(&["movement", "poison"]);
+    /// 
+    /// let cm = manager.get_comp_manager();
+    /// assert_eq!(2, cm.get_components::<Pos>().unwrap().get(&entity).unwrap().0);
+    /// assert_eq!(9, cm.get_components::<Hp>().unwrap().get(&entity).unwrap().0);
+    /// ```
+    pub fn run_parallel(&self, names: &[&str]) {
+        let tasks: Vec<_> = {
+            let schedule = self.schedule.read().unwrap();
+            names.iter().filter_map(|name| schedule.get(name)).collect()
+        };
+        let tasks: Vec<&systems::System> = tasks.iter().map(|task| &**task).collect();
+        systems::run_parallel(self, &tasks, &[]);
+        self.get_comp_manager().update_removed();
+    }
+
+    ///
+    /// Runs every registered system once, stage by stage. Inside a stage the systems are
+    /// sorted by their before and after constraints, and otherwise keep their registration order.
+    /// Systems run in parallel whenever neither their constraints nor their components conflict.
+    /// 
+    /// Returns an error without running anything if the constraints can't be satisfied.
+    /// After the last stage every event channel and removal log is updated,
+    /// see [`Manager::add_event`] and [`RemovedComponents`]. Systems registered or
+    /// configured by a running system take effect from the next call on.
+    ///
+    /// # Panics
+    /// 
+    /// Panics if any of the systems panics, or if the comp_manager is borrowed mutably currently.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    /// 
+    /// let manager = Manager::new();
+    /// 
+    /// manager.register_task("physics", |_, _| {});
+    /// manager.register_task("input", |_, _| {}).in_stage(Stage::PreUpdate);
+    /// manager.register_task("ai", |_, _| {}).after("input").before("physics");
+    /// assert!(manager.run_schedule().is_ok());
+    /// 
+    /// manager.register_task("render", |_, _| {}).after("physics").before("ai");
+    /// assert_eq!(
+    ///     manager.run_schedule().unwrap_err().to_string(),
+    ///     "systems form a cycle: physics -> render -> ai -> physics",
+    /// );
+    /// ```
+    pub fn run_schedule(&self) -> Result<(), ScheduleError> {
+        // The schedule isn't locked while the systems run, so they can register systems.
+        let stages = self.schedule.read().unwrap().order()?;
+        for stage in stages {
+            let tasks: Vec<&systems::System> = stage.systems.iter().map(|task| &**task).collect();
+            systems::run_parallel(self, &tasks, &stage.after);
+        }
+        for update in self.event_updates.read().unwrap().iter() {
+            update(self);
         }
+        self.get_comp_manager().update_removed();
+        Ok(())
     }
 
     pub fn print_components<T: Component>(&self) {
@@ -219,13 +1017,301 @@ impl Manager {
     }
 }
 
+impl Default for Manager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Spawns an entity with the given components in one operation, see [`Manager::add_bundle`].
 #[macro_export]
 macro_rules! entity_with {
     ($m:expr, $($comp:expr),*) => {{
-        let e = crate::Manager::add_entity($m);
-        $(
-            crate::Manager::add_component($m, e, $comp);
-        )*
+        let e = $crate::Manager::add_entity($m);
+        $crate::Manager::add_bundle($m, &e, ($($comp,)*));
         e
     }};
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Pos(i32);
+    struct Vel(i32);
+
+    group!(Pos, Vel);
+
+    #[test]
+    fn manager_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Manager>();
+    }
+
+    #[test]
+    fn spawn_batch() {
+        let manager = Manager::new();
+        let killed = manager.add_entity();
+        manager.kill_entity(killed);
+
+        let entities = manager.spawn_batch((0..10).map(|i| (Pos(i), Vel(i))));
+        assert_eq!(entities.len(), 10);
+        assert_eq!(entities[0], Entity::new(0, 1));
+        assert_eq!(entities[9], Entity::new(9, 0));
+
+        let cm = manager.get_comp_manager();
+        assert_eq!(cm.group_len::<Pos>(), 10);
+        for (entity, pos, vel) in cm.query::<(&Pos, &Vel)>().iter() {
+            assert_eq!(pos.0, vel.0);
+            assert_eq!(entities[pos.0 as usize], entity);
+        }
+        drop(cm);
+
+        let query = manager.query::<(&Pos,)>();
+        assert!(manager.try_spawn_batch(std::iter::once((Pos(0),))).is_err());
+        drop(query);
+        assert_eq!(manager.query::<(&Pos,)>().iter().count(), 10);
+    }
+
+    #[test]
+    fn borrow_errors() {
+        let manager = Manager::new();
+        let entity = manager.add_entity();
+        manager.add_component(&entity, Pos(0));
+
+        let query = manager.query::<(&mut Pos,)>();
+        assert!(matches!(manager.try_query::<(&Pos,)>(), Err(BorrowError::MutablyBorrowed(_))));
+        assert!(matches!(manager.try_remove_component::<Pos>(&entity), Err(BorrowError::Borrowed(_))));
+        assert!(manager.try_kill_entity(entity).is_err());
+        assert!(manager.try_get_comp_manager().is_ok());
+        drop(query);
+
+        assert_eq!(manager.try_remove_component::<Pos>(&entity).unwrap().unwrap().0, 0);
+        assert!(manager.try_kill_entity(entity).is_ok());
+        assert!(!manager.entity_alive(entity));
+    }
+
+    #[test]
+    fn threads() {
+        let manager = Manager::new();
+        for i in 0..100 {
+            let entity = manager.add_entity();
+            manager.add_component(&entity, Pos(i));
+            manager.add_component(&entity, Vel(1));
+        }
+
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    manager.add_entity();
+                    assert_eq!(manager.query::<(&Pos, &Vel)>().iter().count(), 100);
+                });
+            }
+        });
+
+        for (_, pos, vel) in manager.query::<(&mut Pos, &Vel)>().iter() {
+            pos.0 += vel.0;
+        }
+        let cm = manager.get_comp_manager();
+        assert_eq!(cm.group_len::<Pos>(), 100);
+        assert_eq!(cm.get_components::<Pos>().unwrap().get(&Entity::new(0, 0)).unwrap().0, 1);
+    }
+
+    #[test]
+    fn run_parallel() {
+        let manager = Manager::new();
+        for i in 0..100 {
+            let entity = manager.add_entity();
+            manager.add_component(&entity, Pos(i));
+        }
+
+        manager.register_system("spawn", Access::new(), |_, commands| {
+            let entity = commands.spawn();
+            commands.add_component(&entity, Vel(1));
+        });
+        manager.register_system("sum", Access::new().read::<Pos>(), |cm, commands| {
+            let sum: i32 = cm.query::<(&Pos,)>().iter().map(|(_, pos)| pos.0).sum();
+            let entity = commands.spawn();
+            commands.add_component(&entity, Vel(sum));
+        });
+        manager.register_system("move", Access::new().write::<Pos>(), |cm, _| {
+            for (_, pos) in &mut cm.query::<(&mut Pos,)>() {
+                pos.0 += 1;
+            }
+        });
+        manager.run_parallel(&["spawn", "sum", "move", "missing"]);
+
+        let cm = manager.get_comp_manager();
+        let vels: Vec<i32> = cm.query::<(&Vel,)>().iter().map(|(_, vel)| vel.0).collect();
+        assert_eq!(vels.len(), 2);
+        assert!(vels.contains(&(99 * 100 / 2)));
+        assert_eq!(cm.get_components::<Pos>().unwrap().get(&Entity::new(0, 0)).unwrap().0, 1);
+    }
+
+    #[test]
+    fn run_schedule() {
+        use std::sync::{Arc, Mutex};
+
+        let manager = Manager::new();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        for (name, stage) in [("draw", Stage::PostUpdate), ("move", Stage::Update), ("input", Stage::PreUpdate), ("ai", Stage::Update)] {
+            let log = log.clone();
+            manager.register_system(name, Access::new(), move |_, _| log.lock().unwrap().push(name)).in_stage(stage);
+        }
+        manager.register_task("collide", |_, _| {}).after("move").before("ai");
+
+        manager.run_schedule().unwrap();
+        assert_eq!(*log.lock().unwrap(), vec!["input", "move", "ai", "draw"]);
+
+        manager.register_task("collide", |_, _| {}).in_stage(Stage::PreUpdate).after("ai");
+        assert!(matches!(manager.run_schedule(), Err(ScheduleError::StageOrder { .. })));
+    }
+
+    #[test]
+    fn register_while_running() {
+        let manager = Manager::new();
+        manager.register_task("setup", |_, commands| {
+            commands.manager().register_task("spawn", |_, commands| {
+                let entity = commands.spawn();
+                commands.add_component(&entity, Pos(0));
+            });
+        });
+        manager.register_task("reorder", |_, commands| {
+            commands.manager().register_task("setup", |_, _| {}).after("spawn");
+        });
+
+        manager.run_parallel(&["setup"]);
+        manager.run_schedule().unwrap();
+        manager.run_schedule().unwrap();
+        assert_eq!(manager.get_comp_manager().get_components::<Pos>().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn resource_systems() {
+        struct Clock(u32);
+        struct Gravity(i32);
+
+        let manager = Manager::new();
+        manager.insert_resource(Clock(0));
+        manager.insert_resource(Gravity(-1));
+        let entity = manager.add_entity();
+        manager.add_component(&entity, Vel(0));
+
+        manager.add_system("tick", |mut clock: ResMut<Clock>| clock.0 += 1);
+        manager.add_system("fall", |gravity: Res<Gravity>, mut query: Query<(&mut Vel,)>| {
+            for (_, vel) in query.iter() {
+                vel.0 += gravity.0;
+            }
+        });
+        manager.run_schedule().unwrap();
+        manager.run_schedule().unwrap();
+
+        assert_eq!(manager.resource::<Clock>().unwrap().0, 2);
+        assert_eq!(manager.get_comp_manager().get_components::<Vel>().unwrap().get(&entity).unwrap().0, -2);
+        assert!(manager.has_resource::<Gravity>());
+        assert!(!manager.has_resource::<Pos>());
+    }
+
+    #[test]
+    fn events_schedule() {
+        use std::sync::{Arc, Mutex};
+
+        struct Hit(u32);
+
+        let manager = Manager::new();
+        manager.add_event::<Hit>();
+        let early = Arc::new(Mutex::new(Vec::new()));
+        let late = Arc::new(Mutex::new(Vec::new()));
+
+        let log = early.clone();
+        manager.add_system("early", move |mut hits: EventReader<Hit>| {
+            log.lock().unwrap().extend(hits.read().map(|hit| hit.0));
+        });
+        manager.add_system("send", |mut hits: EventWriter<Hit>, mut count: ResMut<u32>| {
+            *count += 1;
+            if *count <= 2 {
+                hits.send(Hit(*count));
+            }
+        }).after("early");
+        let log = late.clone();
+        manager.add_system("late", move |mut hits: EventReader<Hit>| {
+            log.lock().unwrap().extend(hits.read().map(|hit| hit.0));
+        }).after("send");
+        manager.insert_resource(0u32);
+
+        for _ in 0..4 {
+            manager.run_schedule().unwrap();
+        }
+
+        assert_eq!(*early.lock().unwrap(), vec![1, 2]);
+        assert_eq!(*late.lock().unwrap(), vec![1, 2]);
+        assert!(manager.resource::<Events<Hit>>().unwrap().is_empty());
+    }
+
+    #[test]
+    fn removed_components() {
+        use std::sync::{Arc, Mutex};
+
+        let manager = Manager::new();
+        let entities: Vec<Entity> = (0..4).map(|_| manager.add_entity()).collect();
+        for entity in &entities {
+            manager.add_component(entity, Pos(0));
+            manager.add_component(entity, Vel(0));
+        }
+        let early = Arc::new(Mutex::new(Vec::new()));
+        let late = Arc::new(Mutex::new(Vec::new()));
+
+        let log = early.clone();
+        manager.add_system("early", move |mut removed: RemovedComponents<Pos>| {
+            log.lock().unwrap().extend(removed.read().map(|e| e.get_index()));
+        });
+        manager.add_system("remove", |mut commands: Commands, mut count: ResMut<u32>| {
+            *count += 1;
+            if *count == 1 {
+                commands.remove_component::<Pos>(&Entity::new(1, 0));
+                commands.kill_entity(Entity::new(2, 0));
+            }
+        }).after("early");
+        let log = late.clone();
+        manager.add_system("late", move |removed: RemovedComponents<Pos>| {
+            log.lock().unwrap().push(removed.len());
+        }).after("remove");
+        manager.insert_resource(0u32);
+
+        manager.remove_component::<Pos>(&entities[0]);
+        for _ in 0..3 {
+            manager.run_schedule().unwrap();
+        }
+
+        assert_eq!(*early.lock().unwrap(), vec![0, 1, 2]);
+        // The late reader never reads, so it keeps seeing removals until they are dropped.
+        assert_eq!(*late.lock().unwrap(), vec![3, 3, 0]);
+        assert!(manager.get_comp_manager().removed::<Pos>().unwrap().is_empty());
+        // Nothing reads the removals of Vel, so they are never logged.
+        assert!(manager.get_comp_manager().removed::<Vel>().unwrap().is_empty());
+    }
+
+    #[test]
+    fn removal_log_outside_schedule() {
+        use std::sync::{Arc, Mutex};
+
+        let manager = Manager::new();
+        let entity = manager.add_entity();
+        manager.add_component(&entity, Pos(0));
+        manager.remove_component::<Pos>(&entity);
+        assert!(manager.get_comp_manager().removed::<Pos>().unwrap().is_empty());
+
+        let lens = Arc::new(Mutex::new(Vec::new()));
+        let log = lens.clone();
+        manager.add_system("reader", move |mut removed: RemovedComponents<Pos>| {
+            let before = removed.len();
+            assert_eq!(removed.read().count(), before);
+            log.lock().unwrap().push((before, removed.len(), removed.read().count()));
+        });
+        manager.add_component(&entity, Pos(0));
+        manager.remove_component::<Pos>(&entity);
+        manager.run_task("reader");
+        manager.run_parallel(&["reader"]);
+        assert_eq!(*lens.lock().unwrap(), vec![(1, 0, 0), (0, 0, 0)]);
+        assert!(manager.get_comp_manager().removed::<Pos>().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,952 @@
+use std::any::TypeId;
+use std::marker::PhantomData;
+
+use super::cm::{View, ViewMut};
+use super::cell::{AtomicRef, BorrowError};
+use crate::sparse_set::{ComponentTicks, SparseSet};
+use crate::{Access, Component, ComponentManager, Entity};
+
+///
+/// The ticks a query compares change ticks against. Entries added or changed after `last_run`
+/// match [`Added`] and [`Changed`], and entries fetched mutably are stamped with `this_run`.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Ticks {
+    pub last_run: u32,
+    pub this_run: u32,
+}
+
+///
+/// A single component access in a query. Implemented for `&T`, `&mut T` and `Option` of those.
+/// Optional fetches never drive an iteration, so a query needs at least one required fetch to match anything.
+///
+pub trait Fetch {
+    /// The borrow guards held for as long as the query lives.
+    type Borrow<'w>;
+    /// Shared access to the borrowed set during one iteration.
+    type Column<'q>;
+    /// The value handed out for every matching entity.
+    type Item<'q>;
+
+    /// Borrows the underlying set. Returns None if nothing can match.
+    fn borrow(cm: &ComponentManager) -> Result<Option<Self::Borrow<'_>>, BorrowError>;
+
+    fn column<'q>(borrow: &'q mut Self::Borrow<'_>, ticks: Ticks) -> Self::Column<'q>;
+
+    /// Amount of entities in the column, or None if the column can't drive an iteration.
+    fn len(column: &Self::Column<'_>) -> Option<usize>;
+
+    fn key_at(column: &Self::Column<'_>, index: usize) -> Option<Entity>;
+
+    /// The fetched component, if the fetch can take part in a grouped iteration.
+    fn component_type() -> Option<TypeId>;
+
+    /// Returns true if `types` are exactly the full group of the fetched component.
+    fn grouped_by(types: &[TypeId]) -> bool;
+
+    fn group_len(column: &Self::Column<'_>) -> usize;
+
+    /// Declares the component the fetch reads or writes.
+    fn access(access: Access) -> Access;
+
+    ///
+    /// Fetches the item belonging to the entity.
+    ///
+    /// # Safety
+    ///
+    /// Every entity may only be fetched once per column, since mutable fetches hand out `&mut`.
+    unsafe fn fetch<'q>(column: &Self::Column<'q>, entity: &Entity) -> Option<Self::Item<'q>>;
+
+    ///
+    /// Fetches the item at the given position in the packed array.
+    ///
+    /// # Safety
+    ///
+    /// Every index may only be fetched once per column, since mutable fetches hand out `&mut`.
+    unsafe fn fetch_at<'q>(column: &Self::Column<'q>, index: usize) -> Option<Self::Item<'q>>;
+}
+
+impl<T: Component> Fetch for &T {
+    type Borrow<'w> = View<'w, T>;
+    type Column<'q> = &'q SparseSet<Entity, T>;
+    type Item<'q> = &'q T;
+
+    fn borrow(cm: &ComponentManager) -> Result<Option<Self::Borrow<'_>>, BorrowError> {
+        cm.try_get_components::<T>()
+    }
+
+    fn column<'q>(borrow: &'q mut Self::Borrow<'_>, _: Ticks) -> Self::Column<'q> {
+        &**borrow
+    }
+
+    fn len(column: &Self::Column<'_>) -> Option<usize> {
+        Some(column.len())
+    }
+
+    fn key_at(column: &Self::Column<'_>, index: usize) -> Option<Entity> {
+        column.key_at(index).copied()
+    }
+
+    fn component_type() -> Option<TypeId> {
+        Some(TypeId::of::<T>())
+    }
+
+    fn grouped_by(types: &[TypeId]) -> bool {
+        T::grouped_by(types)
+    }
+
+    fn group_len(column: &Self::Column<'_>) -> usize {
+        column.group_len()
+    }
+
+    fn access(access: Access) -> Access {
+        access.read::<T>()
+    }
+
+    unsafe fn fetch<'q>(column: &Self::Column<'q>, entity: &Entity) -> Option<Self::Item<'q>> {
+        column.get(entity)
+    }
+
+    unsafe fn fetch_at<'q>(column: &Self::Column<'q>, index: usize) -> Option<Self::Item<'q>> {
+        column.component_at(index)
+    }
+}
+
+///
+/// Column handing out mutable components. Keeps raw pointers so several
+/// components of the same set can be borrowed at once. Marks every fetched component changed.
+///
+pub struct WriteColumn<'q, T> {
+    set: *const SparseSet<Entity, T>,
+    components: *mut T,
+    ticks: *mut ComponentTicks,
+    this_run: u32,
+    _marker: PhantomData<&'q mut SparseSet<Entity, T>>,
+}
+
+impl<T: Component> Fetch for &mut T {
+    type Borrow<'w> = ViewMut<'w, T>;
+    type Column<'q> = WriteColumn<'q, T>;
+    type Item<'q> = &'q mut T;
+
+    fn borrow(cm: &ComponentManager) -> Result<Option<Self::Borrow<'_>>, BorrowError> {
+        cm.try_get_components_mut::<T>()
+    }
+
+    fn column<'q>(borrow: &'q mut Self::Borrow<'_>, ticks: Ticks) -> Self::Column<'q> {
+        let set: &'q mut SparseSet<Entity, T> = borrow;
+        let components = set.components_ptr_mut();
+        let ticks_ptr = set.ticks_ptr_mut();
+        WriteColumn {
+            set,
+            components,
+            ticks: ticks_ptr,
+            this_run: ticks.this_run,
+            _marker: PhantomData,
+        }
+    }
+
+    fn len(column: &Self::Column<'_>) -> Option<usize> {
+        // Safety: the set is exclusively borrowed by the column for 'q and never moved or resized.
+        Some(unsafe { (*column.set).len() })
+    }
+
+    fn key_at(column: &Self::Column<'_>, index: usize) -> Option<Entity> {
+        unsafe { (*column.set).key_at(index).copied() }
+    }
+
+    fn component_type() -> Option<TypeId> {
+        Some(TypeId::of::<T>())
+    }
+
+    fn grouped_by(types: &[TypeId]) -> bool {
+        T::grouped_by(types)
+    }
+
+    fn group_len(column: &Self::Column<'_>) -> usize {
+        unsafe { (*column.set).group_len() }
+    }
+
+    fn access(access: Access) -> Access {
+        access.write::<T>()
+    }
+
+    unsafe fn fetch<'q>(column: &Self::Column<'q>, entity: &Entity) -> Option<Self::Item<'q>> {
+        let index = (*column.set).index_of(entity)?;
+        Self::fetch_at(column, index)
+    }
+
+    unsafe fn fetch_at<'q>(column: &Self::Column<'q>, index: usize) -> Option<Self::Item<'q>> {
+        if index < (*column.set).len() {
+            (*column.ticks.add(index)).changed = column.this_run;
+            Some(&mut *column.components.add(index))
+        } else {
+            None
+        }
+    }
+}
+
+impl<F: Fetch> Fetch for Option<F> {
+    type Borrow<'w> = Option<F::Borrow<'w>>;
+    type Column<'q> = Option<F::Column<'q>>;
+    type Item<'q> = Option<F::Item<'q>>;
+
+    fn borrow(cm: &ComponentManager) -> Result<Option<Self::Borrow<'_>>, BorrowError> {
+        F::borrow(cm).map(Some)
+    }
+
+    fn column<'q>(borrow: &'q mut Self::Borrow<'_>, ticks: Ticks) -> Self::Column<'q> {
+        borrow.as_mut().map(|borrow| F::column(borrow, ticks))
+    }
+
+    fn len(_: &Self::Column<'_>) -> Option<usize> {
+        None
+    }
+
+    fn key_at(_: &Self::Column<'_>, _: usize) -> Option<Entity> {
+        None
+    }
+
+    fn component_type() -> Option<TypeId> {
+        None
+    }
+
+    fn grouped_by(_: &[TypeId]) -> bool {
+        false
+    }
+
+    fn group_len(_: &Self::Column<'_>) -> usize {
+        0
+    }
+
+    fn access(access: Access) -> Access {
+        F::access(access)
+    }
+
+    unsafe fn fetch<'q>(column: &Self::Column<'q>, entity: &Entity) -> Option<Self::Item<'q>> {
+        match column {
+            Some(column) => Some(F::fetch(column, entity)),
+            None => Some(None),
+        }
+    }
+
+    /// Optional fetches never take part in grouped iterations.
+    unsafe fn fetch_at<'q>(_: &Self::Column<'q>, _: usize) -> Option<Self::Item<'q>> {
+        None
+    }
+}
+
+///
+/// A tuple of fetches that can be queried together, e.g. `(&A, &mut B)`.
+/// Items are yielded as `(Entity, &A, &mut B)`.
+///
+pub trait QueryData {
+    type Borrow<'w>;
+    type Columns<'q>;
+    type Item<'q>;
+
+    /// Borrows every set. Returns None if nothing can match.
+    fn borrow(cm: &ComponentManager) -> Result<Option<Self::Borrow<'_>>, BorrowError>;
+
+    fn columns<'q>(borrow: &'q mut Self::Borrow<'_>, ticks: Ticks) -> Self::Columns<'q>;
+
+    /// Picks the column with the fewest entities. Returns its position in the tuple and its length.
+    fn driver(columns: &Self::Columns<'_>) -> Option<(usize, usize)>;
+
+    fn key_at(columns: &Self::Columns<'_>, driver: usize, index: usize) -> Option<Entity>;
+
+    /// Returns the group length if the fetched components are exactly one full group.
+    fn group_len(columns: &Self::Columns<'_>) -> Option<usize>;
+
+    /// Declares the components read and written by the query.
+    fn access(access: Access) -> Access;
+
+    ///
+    /// Fetches all components of the entity, or None if any of them is missing.
+    ///
+    /// # Safety
+    ///
+    /// Every entity may only be fetched once per set of columns.
+    unsafe fn fetch<'q>(columns: &Self::Columns<'q>, entity: &Entity) -> Option<Self::Item<'q>>;
+
+    ///
+    /// Fetches the components at the same position of every set. Only valid inside a full group.
+    ///
+    /// # Safety
+    ///
+    /// Every index may only be fetched once per set of columns.
+    unsafe fn fetch_at<'q>(columns: &Self::Columns<'q>, index: usize) -> Option<Self::Item<'q>>;
+}
+
+macro_rules! impl_query_data {
+    ($(($fetch:ident, $index:tt)),+) => {
+        impl<$($fetch: Fetch),+> QueryData for ($($fetch,)+) {
+            type Borrow<'w> = ($($fetch::Borrow<'w>,)+);
+            type Columns<'q> = ($($fetch::Column<'q>,)+);
+            type Item<'q> = (Entity, $($fetch::Item<'q>,)+);
+
+            fn borrow(cm: &ComponentManager) -> Result<Option<Self::Borrow<'_>>, BorrowError> {
+                // Every set is borrowed before giving up, so conflicts are reported even if nothing matches.
+                let borrow = ($($fetch::borrow(cm)?,)+);
+                Ok(Some(($(match borrow.$index {
+                    Some(borrow) => borrow,
+                    None => return Ok(None),
+                },)+)))
+            }
+
+            fn columns<'q>(borrow: &'q mut Self::Borrow<'_>, ticks: Ticks) -> Self::Columns<'q> {
+                ($($fetch::column(&mut borrow.$index, ticks),)+)
+            }
+
+            fn driver(columns: &Self::Columns<'_>) -> Option<(usize, usize)> {
+                let mut driver: Option<(usize, usize)> = None;
+                $(
+                if let Some(len) = $fetch::len(&columns.$index) {
+                    match driver {
+                        Some((_, min)) if min <= len => {},
+                        _ => driver = Some(($index, len)),
+                    }
+                }
+                )+
+                driver
+            }
+
+            fn key_at(columns: &Self::Columns<'_>, driver: usize, index: usize) -> Option<Entity> {
+                match driver {
+                    $($index => $fetch::key_at(&columns.$index, index),)+
+                    _ => None,
+                }
+            }
+
+            fn group_len(columns: &Self::Columns<'_>) -> Option<usize> {
+                let types = [$($fetch::component_type()?,)+];
+                if $($fetch::grouped_by(&types))&&+ {
+                    [$($fetch::group_len(&columns.$index),)+].iter().min().copied()
+                } else {
+                    None
+                }
+            }
+
+            fn access(access: Access) -> Access {
+                $(let access = $fetch::access(access);)+
+                access
+            }
+
+            unsafe fn fetch<'q>(columns: &Self::Columns<'q>, entity: &Entity) -> Option<Self::Item<'q>> {
+                Some((*entity, $($fetch::fetch(&columns.$index, entity)?,)+))
+            }
+
+            unsafe fn fetch_at<'q>(columns: &Self::Columns<'q>, index: usize) -> Option<Self::Item<'q>> {
+                let entity = Self::key_at(columns, 0, index)?;
+                Some((entity, $($fetch::fetch_at(&columns.$index, index)?,)+))
+            }
+        }
+    };
+}
+
+impl_query_data!((A, 0));
+impl_query_data!((A, 0), (B, 1));
+impl_query_data!((A, 0), (B, 1), (C, 2));
+impl_query_data!((A, 0), (B, 1), (C, 2), (D, 3));
+impl_query_data!((A, 0), (B, 1), (C, 2), (D, 3), (E, 4));
+impl_query_data!((A, 0), (B, 1), (C, 2), (D, 3), (E, 4), (F, 5));
+impl_query_data!((A, 0), (B, 1), (C, 2), (D, 3), (E, 4), (F, 5), (G, 6));
+impl_query_data!((A, 0), (B, 1), (C, 2), (D, 3), (E, 4), (F, 5), (G, 6), (H, 7));
+
+///
+/// A filter deciding which entities a query yields without fetching any components.
+/// Implemented for `()`, [`With`], [`Without`], [`Added`], [`Changed`], [`Or`]
+/// and tuples of filters, which all have to match.
+///
+pub trait QueryFilter {
+    type Borrow<'w>;
+    type Column<'q>;
+
+    fn borrow(cm: &ComponentManager) -> Result<Self::Borrow<'_>, BorrowError>;
+
+    fn column<'q>(borrow: &'q Self::Borrow<'_>, ticks: Ticks) -> Self::Column<'q>;
+
+    fn matches(column: &Self::Column<'_>, entity: &Entity) -> bool;
+
+    /// Declares the components read by the filter.
+    fn access(access: Access) -> Access;
+}
+
+/// Matches entities having the component `T`.
+pub struct With<T>(PhantomData<T>);
+
+/// Matches entities not having the component `T`.
+pub struct Without<T>(PhantomData<T>);
+
+///
+/// Matches entities whose component `T` was added since the system last ran.
+/// Outside of systems every component counts as added.
+///
+pub struct Added<T>(PhantomData<T>);
+
+///
+/// Matches entities whose component `T` was added or fetched mutably since the system last ran.
+/// Outside of systems every component counts as changed.
+///
+pub struct Changed<T>(PhantomData<T>);
+
+/// Matches entities matching any of the filters in the tuple `T`.
+pub struct Or<T>(PhantomData<T>);
+
+impl QueryFilter for () {
+    type Borrow<'w> = ();
+    type Column<'q> = ();
+
+    fn borrow(_: &ComponentManager) -> Result<Self::Borrow<'_>, BorrowError> {
+        Ok(())
+    }
+
+    fn column<'q>(_: &'q Self::Borrow<'_>, _: Ticks) -> Self::Column<'q> {}
+
+    fn matches(_: &Self::Column<'_>, _: &Entity) -> bool {
+        true
+    }
+
+    fn access(access: Access) -> Access {
+        access
+    }
+}
+
+impl<T: Component> QueryFilter for With<T> {
+    type Borrow<'w> = Option<View<'w, T>>;
+    type Column<'q> = Option<&'q SparseSet<Entity, T>>;
+
+    fn borrow(cm: &ComponentManager) -> Result<Self::Borrow<'_>, BorrowError> {
+        cm.try_get_components::<T>()
+    }
+
+    fn column<'q>(borrow: &'q Self::Borrow<'_>, _: Ticks) -> Self::Column<'q> {
+        borrow.as_deref()
+    }
+
+    fn matches(column: &Self::Column<'_>, entity: &Entity) -> bool {
+        column.is_some_and(|set| set.contains(entity))
+    }
+
+    fn access(access: Access) -> Access {
+        access.read::<T>()
+    }
+}
+
+impl<T: Component> QueryFilter for Without<T> {
+    type Borrow<'w> = Option<View<'w, T>>;
+    type Column<'q> = Option<&'q SparseSet<Entity, T>>;
+
+    fn borrow(cm: &ComponentManager) -> Result<Self::Borrow<'_>, BorrowError> {
+        cm.try_get_components::<T>()
+    }
+
+    fn column<'q>(borrow: &'q Self::Borrow<'_>, _: Ticks) -> Self::Column<'q> {
+        borrow.as_deref()
+    }
+
+    fn matches(column: &Self::Column<'_>, entity: &Entity) -> bool {
+        !column.is_some_and(|set| set.contains(entity))
+    }
+
+    fn access(access: Access) -> Access {
+        access.read::<T>()
+    }
+}
+
+impl<T: Component> QueryFilter for Added<T> {
+    type Borrow<'w> = Option<View<'w, T>>;
+    type Column<'q> = Option<(&'q SparseSet<Entity, T>, Ticks)>;
+
+    fn borrow(cm: &ComponentManager) -> Result<Self::Borrow<'_>, BorrowError> {
+        cm.try_get_components::<T>()
+    }
+
+    fn column<'q>(borrow: &'q Self::Borrow<'_>, ticks: Ticks) -> Self::Column<'q> {
+        borrow.as_deref().map(|set| (set, ticks))
+    }
+
+    fn matches(column: &Self::Column<'_>, entity: &Entity) -> bool {
+        column.is_some_and(|(set, ticks)| {
+            set.ticks(entity).is_some_and(|t| t.is_added(ticks.last_run, ticks.this_run))
+        })
+    }
+
+    fn access(access: Access) -> Access {
+        access.read::<T>()
+    }
+}
+
+impl<T: Component> QueryFilter for Changed<T> {
+    type Borrow<'w> = Option<View<'w, T>>;
+    type Column<'q> = Option<(&'q SparseSet<Entity, T>, Ticks)>;
+
+    fn borrow(cm: &ComponentManager) -> Result<Self::Borrow<'_>, BorrowError> {
+        cm.try_get_components::<T>()
+    }
+
+    fn column<'q>(borrow: &'q Self::Borrow<'_>, ticks: Ticks) -> Self::Column<'q> {
+        borrow.as_deref().map(|set| (set, ticks))
+    }
+
+    fn matches(column: &Self::Column<'_>, entity: &Entity) -> bool {
+        column.is_some_and(|(set, ticks)| {
+            set.ticks(entity).is_some_and(|t| t.is_changed(ticks.last_run, ticks.this_run))
+        })
+    }
+
+    fn access(access: Access) -> Access {
+        access.read::<T>()
+    }
+}
+
+macro_rules! impl_query_filter {
+    ($(($filter:ident, $index:tt)),+) => {
+        impl<$($filter: QueryFilter),+> QueryFilter for ($($filter,)+) {
+            type Borrow<'w> = ($($filter::Borrow<'w>,)+);
+            type Column<'q> = ($($filter::Column<'q>,)+);
+
+            fn borrow(cm: &ComponentManager) -> Result<Self::Borrow<'_>, BorrowError> {
+                Ok(($($filter::borrow(cm)?,)+))
+            }
+
+            fn column<'q>(borrow: &'q Self::Borrow<'_>, ticks: Ticks) -> Self::Column<'q> {
+                ($($filter::column(&borrow.$index, ticks),)+)
+            }
+
+            fn matches(column: &Self::Column<'_>, entity: &Entity) -> bool {
+                $($filter::matches(&column.$index, entity))&&+
+            }
+
+            fn access(access: Access) -> Access {
+                $(let access = $filter::access(access);)+
+                access
+            }
+        }
+
+        impl<$($filter: QueryFilter),+> QueryFilter for Or<($($filter,)+)> {
+            type Borrow<'w> = ($($filter::Borrow<'w>,)+);
+            type Column<'q> = ($($filter::Column<'q>,)+);
+
+            fn borrow(cm: &ComponentManager) -> Result<Self::Borrow<'_>, BorrowError> {
+                Ok(($($filter::borrow(cm)?,)+))
+            }
+
+            fn column<'q>(borrow: &'q Self::Borrow<'_>, ticks: Ticks) -> Self::Column<'q> {
+                ($($filter::column(&borrow.$index, ticks),)+)
+            }
+
+            fn matches(column: &Self::Column<'_>, entity: &Entity) -> bool {
+                $($filter::matches(&column.$index, entity))||+
+            }
+
+            fn access(access: Access) -> Access {
+                $(let access = $filter::access(access);)+
+                access
+            }
+        }
+    };
+}
+
+impl_query_filter!((A, 0));
+impl_query_filter!((A, 0), (B, 1));
+impl_query_filter!((A, 0), (B, 1), (C, 2));
+impl_query_filter!((A, 0), (B, 1), (C, 2), (D, 3));
+impl_query_filter!((A, 0), (B, 1), (C, 2), (D, 3), (E, 4));
+impl_query_filter!((A, 0), (B, 1), (C, 2), (D, 3), (E, 4), (F, 5));
+impl_query_filter!((A, 0), (B, 1), (C, 2), (D, 3), (E, 4), (F, 5), (G, 6));
+impl_query_filter!((A, 0), (B, 1), (C, 2), (D, 3), (E, 4), (F, 5), (G, 6), (H, 7));
+
+///
+/// Borrowed view over all entities having every required component in `Q` and matching the filter `F`.
+/// The component sets are borrowed once when the query is created and released when it is dropped.
+///
+/// # Examples
+///
+/// ```
+/// use rust_ecs::*;
+/// struct Pos(i32);
+/// struct Vel(i32);
+///
+/// register_components!(Pos, Vel);
+///
+/// let manager = Manager::new();
+///
+/// let entity = manager.add_entity();
+/// manager.add_component(&entity, Pos(0));
+/// manager.add_component(&entity, Vel(2));
+///
+/// let mut query = manager.query::<(&mut Pos, &Vel)>();
+/// for (_, pos, vel) in query.iter() {
+///     pos.0 += vel.0;
+/// }
+/// ```
+pub struct Query<'w, Q: QueryData, F: QueryFilter = ()> {
+    // Declared before the manager guard so the set borrows are released first.
+    borrow: Option<Q::Borrow<'w>>,
+    filter: F::Borrow<'w>,
+    ticks: Ticks,
+    _guard: Option<AtomicRef<'w, ComponentManager>>,
+}
+
+impl<'w, Q: QueryData, F: QueryFilter> Query<'w, Q, F> {
+    /// Creates a query outside of any system, to which every component counts as added and changed.
+    pub(crate) fn new(cm: &'w ComponentManager) -> Result<Self, BorrowError> {
+        Self::with_ticks(cm, Ticks { last_run: 0, this_run: cm.change_tick() })
+    }
+
+    /// Creates a query for a system that last ran at `ticks.last_run`.
+    pub(crate) fn with_ticks(cm: &'w ComponentManager, ticks: Ticks) -> Result<Self, BorrowError> {
+        Ok(Query {
+            borrow: Q::borrow(cm)?,
+            filter: F::borrow(cm)?,
+            ticks,
+            _guard: None,
+        })
+    }
+
+    /// Creates a query that keeps the manager borrowed through `guard` while it lives.
+    pub(crate) fn guarded(guard: AtomicRef<'w, ComponentManager>, cm: &'w ComponentManager) -> Result<Self, BorrowError> {
+        let mut query = Self::new(cm)?;
+        query._guard = Some(guard);
+        Ok(query)
+    }
+
+    ///
+    /// Iterates all matching entities. If the queried components are exactly one full group,
+    /// the grouped part of the sets is walked in lockstep without any lookups.
+    /// Otherwise the iteration is driven by the smallest of the queried sets.
+    ///
+    pub fn iter(&mut self) -> QueryIter<'_, Q, F> {
+        let ticks = self.ticks;
+        let columns = self.borrow.as_mut().map(|borrow| Q::columns(borrow, ticks));
+        let group_len = columns.as_ref().and_then(|c| Q::group_len(c));
+        let (driver, len) = match group_len {
+            Some(len) => (0, len),
+            None => columns.as_ref().and_then(|c| Q::driver(c)).unwrap_or((0, 0)),
+        };
+        QueryIter {
+            columns,
+            filter: F::column(&self.filter, ticks),
+            driver,
+            index: 0,
+            len,
+            grouped: group_len.is_some(),
+        }
+    }
+
+    ///
+    /// Returns true if the query matches a full group and iterates it without lookups.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    /// struct Pos(i32);
+    /// struct Vel(i32);
+    ///
+    /// group!(Pos, Vel);
+    ///
+    /// let manager = Manager::new();
+    /// let entity = manager.add_entity();
+    /// manager.add_component(&entity, Pos(0));
+    /// manager.add_component(&entity, Vel(2));
+    ///
+    /// assert!(manager.query::<(&Vel, &mut Pos)>().is_grouped());
+    /// assert!(!manager.query::<(&Pos,)>().is_grouped());
+    /// ```
+    pub fn is_grouped(&mut self) -> bool {
+        self.iter().grouped
+    }
+}
+
+impl<'q, Q: QueryData, F: QueryFilter> IntoIterator for &'q mut Query<'_, Q, F> {
+    type Item = Q::Item<'q>;
+    type IntoIter = QueryIter<'q, Q, F>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct QueryIter<'q, Q: QueryData, F: QueryFilter = ()> {
+    columns: Option<Q::Columns<'q>>,
+    filter: F::Column<'q>,
+    driver: usize,
+    index: usize,
+    len: usize,
+    grouped: bool,
+}
+
+impl<'q, Q: QueryData, F: QueryFilter> Iterator for QueryIter<'q, Q, F> {
+    type Item = Q::Item<'q>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let columns = self.columns.as_ref()?;
+        while self.index < self.len {
+            let entity = Q::key_at(columns, self.driver, self.index)?;
+            self.index += 1;
+            if !F::matches(&self.filter, &entity) {
+                continue;
+            }
+            // Safety: every index is visited once and the driving set holds every entity once,
+            // so no entity is fetched twice.
+            let item = if self.grouped {
+                unsafe { Q::fetch_at(columns, self.index - 1) }
+            } else {
+                unsafe { Q::fetch(columns, &entity) }
+            };
+            if item.is_some() {
+                return item;
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Pos(i32);
+    #[derive(Debug, PartialEq)]
+    struct Vel(i32);
+    struct Tag;
+
+    register_components!(Pos, Vel, Tag);
+
+    fn setup() -> ComponentManager {
+        let mut cm = ComponentManager::new();
+        for i in 0..10 {
+            cm.add_component(&Entity::new(i, 0), Pos(i as i32));
+            if i % 2 == 0 {
+                cm.add_component(&Entity::new(i, 0), Vel(1));
+            }
+            if i == 4 {
+                cm.add_component(&Entity::new(i, 0), Tag);
+            }
+        }
+        cm
+    }
+
+    #[test]
+    fn query_single() {
+        let cm = setup();
+        let mut query = cm.query::<(&Pos,)>();
+        assert_eq!(query.iter().count(), 10);
+    }
+
+    #[test]
+    fn query_intersection() {
+        let cm = setup();
+        let mut query = cm.query::<(&Pos, &Vel)>();
+        let mut entities: Vec<u32> = query.iter().map(|(e, _, _)| e.get_index()).collect();
+        entities.sort();
+        assert_eq!(entities, vec![0, 2, 4, 6, 8]);
+    }
+
+    #[test]
+    fn query_mutate() {
+        let cm = setup();
+        for (_, pos, vel) in &mut cm.query::<(&mut Pos, &Vel)>() {
+            pos.0 += vel.0;
+        }
+        let pos = cm.get_components::<Pos>().unwrap();
+        assert_eq!(pos.get(&Entity::new(2, 0)), Some(&Pos(3)));
+        assert_eq!(pos.get(&Entity::new(3, 0)), Some(&Pos(3)));
+    }
+
+    #[test]
+    fn query_collect_mut() {
+        let cm = setup();
+        let mut query = cm.query::<(&mut Pos, &mut Vel)>();
+        let items: Vec<_> = query.iter().collect();
+        assert_eq!(items.len(), 5);
+        for (_, pos, vel) in items {
+            vel.0 = pos.0;
+        }
+        drop(query);
+        let vel = cm.get_components::<Vel>().unwrap();
+        assert_eq!(vel.get(&Entity::new(8, 0)), Some(&Vel(8)));
+    }
+
+    #[test]
+    fn query_smallest_driver() {
+        let cm = setup();
+        let mut query = cm.query::<(&Pos, &Vel, &Tag)>();
+        {
+            let columns = <(&Pos, &Vel, &Tag)>::columns(query.borrow.as_mut().unwrap(), query.ticks);
+            assert_eq!(<(&Pos, &Vel, &Tag)>::driver(&columns), Some((2, 1)));
+        }
+        let entities: Vec<Entity> = query.iter().map(|(e, _, _, _)| e).collect();
+        assert_eq!(entities, vec![Entity::new(4, 0)]);
+    }
+
+    #[test]
+    fn query_missing_family() {
+        struct Unused;
+        register_components!(Unused);
+
+        let cm = setup();
+        assert_eq!(cm.query::<(&Pos, &Unused)>().iter().count(), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn query_conflicting_borrow() {
+        let cm = setup();
+        let _query = cm.query::<(&mut Pos, &Pos)>();
+    }
+
+    struct GPos(i32);
+    struct GVel(i32);
+
+    group!(GPos, GVel);
+
+    fn setup_grouped() -> ComponentManager {
+        let mut cm = ComponentManager::new();
+        for i in 0..10 {
+            cm.add_component(&Entity::new(i, 0), GPos(i as i32));
+            if i % 3 != 0 {
+                cm.add_component(&Entity::new(i, 0), GVel(i as i32));
+            }
+        }
+        cm
+    }
+
+    #[test]
+    fn query_grouped() {
+        let cm = setup_grouped();
+        assert_eq!(cm.group_len::<GPos>(), 6);
+
+        let mut query = cm.query::<(&GVel, &mut GPos)>();
+        assert!(query.is_grouped());
+        let mut entities = Vec::new();
+        for (e, vel, pos) in query.iter() {
+            assert_eq!(vel.0, pos.0);
+            pos.0 += 1;
+            entities.push(e.get_index());
+        }
+        entities.sort();
+        assert_eq!(entities, vec![1, 2, 4, 5, 7, 8]);
+    }
+
+    #[test]
+    fn query_grouped_after_remove() {
+        let cm = setup_grouped();
+        cm.remove_component::<GVel>(&Entity::new(4, 0));
+        cm.remove_component::<GPos>(&Entity::new(1, 0));
+
+        let mut query = cm.query::<(&GPos, &GVel)>();
+        assert!(query.is_grouped());
+        let mut entities: Vec<u32> = query.iter().map(|(e, pos, vel)| {
+            assert_eq!(pos.0, vel.0);
+            e.get_index()
+        }).collect();
+        entities.sort();
+        assert_eq!(entities, vec![2, 5, 7, 8]);
+    }
+
+    #[test]
+    fn query_not_grouped() {
+        let cm = setup_grouped();
+        assert!(!cm.query::<(&GPos,)>().is_grouped());
+        assert!(!cm.query::<(&GPos, &GPos)>().is_grouped());
+        assert!(!cm.query::<(&Pos, &Vel)>().is_grouped());
+        assert_eq!(cm.query::<(&GPos,)>().iter().count(), 10);
+    }
+
+    #[test]
+    fn query_with() {
+        let cm = setup();
+        let entities: Vec<Entity> = cm.query_filtered::<(&Pos,), With<Tag>>().iter().map(|(e, _)| e).collect();
+        assert_eq!(entities, vec![Entity::new(4, 0)]);
+    }
+
+    #[test]
+    fn query_without() {
+        let cm = setup();
+        let mut query = cm.query_filtered::<(&Pos, &Vel), Without<Tag>>();
+        let mut entities: Vec<u32> = query.iter().map(|(e, _, _)| e.get_index()).collect();
+        entities.sort();
+        assert_eq!(entities, vec![0, 2, 6, 8]);
+    }
+
+    #[test]
+    fn query_or() {
+        let cm = setup();
+        let mut query = cm.query_filtered::<(&Pos,), (Without<Vel>, Or<(With<Tag>, With<Pos>)>)>();
+        assert_eq!(query.iter().count(), 5);
+        let mut query = cm.query_filtered::<(&Pos,), Or<(With<Tag>, Without<Vel>)>>();
+        let mut entities: Vec<u32> = query.iter().map(|(e, _)| e.get_index()).collect();
+        entities.sort();
+        assert_eq!(entities, vec![1, 3, 4, 5, 7, 9]);
+    }
+
+    #[test]
+    fn query_filter_missing_family() {
+        struct Unused;
+        register_components!(Unused);
+
+        let cm = setup();
+        assert_eq!(cm.query_filtered::<(&Pos,), With<Unused>>().iter().count(), 0);
+        assert_eq!(cm.query_filtered::<(&Pos,), Without<Unused>>().iter().count(), 10);
+    }
+
+    #[test]
+    fn query_added_changed() {
+        let mut cm = setup();
+        let last_run = cm.increment_change_tick();
+        cm.add_component(&Entity::new(10, 0), Pos(10));
+        for (_, pos) in &mut cm.query_filtered::<(&mut Pos,), With<Tag>>() {
+            pos.0 += 1;
+        }
+        let ticks = Ticks { last_run, this_run: cm.increment_change_tick() };
+
+        let mut added = Query::<(&Pos,), Added<Pos>>::with_ticks(&cm, ticks).unwrap();
+        let entities: Vec<u32> = added.iter().map(|(e, _)| e.get_index()).collect();
+        assert_eq!(entities, vec![10]);
+        let mut changed = Query::<(&Pos,), Changed<Pos>>::with_ticks(&cm, ticks).unwrap();
+        let mut entities: Vec<u32> = changed.iter().map(|(e, _)| e.get_index()).collect();
+        entities.sort();
+        assert_eq!(entities, vec![4, 10]);
+        let mut vel = Query::<(&Pos,), Or<(Changed<Vel>, Without<Vel>)>>::with_ticks(&cm, ticks).unwrap();
+        assert_eq!(vel.iter().count(), 6);
+
+        assert_eq!(cm.query_filtered::<(&Pos,), Added<Pos>>().iter().count(), 11);
+    }
+
+    #[test]
+    fn query_optional() {
+        let cm = setup();
+        let mut query = cm.query::<(&Pos, Option<&mut Vel>)>();
+        let mut with_vel = 0;
+        for (e, _, vel) in query.iter() {
+            if let Some(vel) = vel {
+                assert_eq!(e.get_index() % 2, 0);
+                vel.0 = 2;
+                with_vel += 1;
+            }
+        }
+        assert_eq!(with_vel, 5);
+        assert_eq!(query.iter().count(), 10);
+    }
+
+    #[test]
+    fn query_only_optional() {
+        let cm = setup();
+        assert_eq!(cm.query::<(Option<&Pos>,)>().iter().count(), 0);
+    }
+
+    #[test]
+    fn query_grouped_filtered() {
+        let cm = setup_grouped();
+        let mut query = cm.query_filtered::<(&GPos, &GVel), Without<Tag>>();
+        assert!(query.is_grouped());
+        assert_eq!(query.iter().count(), 6);
+        let mut query = cm.query_filtered::<(&GPos, &GVel), With<Tag>>();
+        assert_eq!(query.iter().count(), 0);
+        assert!(!cm.query::<(&GPos, &GVel, Option<&Tag>)>().is_grouped());
+    }
+}
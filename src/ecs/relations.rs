@@ -0,0 +1,270 @@
+use std::collections::HashMap;
+
+use crate::sparse_set::SparseSet;
+use crate::{Entity, Manager};
+
+///
+/// What happens to the relations pointing at an entity when it is killed.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum OnTargetKilled {
+    /// The relations are removed from their sources.
+    #[default]
+    Remove,
+    /// The sources are killed as well, which cascades through their own relations.
+    Kill,
+    /// The relations are kept and point at the dead entity.
+    Dangle,
+}
+
+///
+/// A relation from a source entity to a target entity, like `Likes` or `Targets`. A source
+/// can have the same relation to any number of targets, each with its own value.
+/// Relations of a killed source are always removed, see [`OnTargetKilled`] for killed targets.
+///
+/// # Examples
+///
+/// ```
+/// use rust_ecs::*;
+/// struct Likes(u32);
+/// struct ChildOf;
+///
+/// impl Relation for Likes {}
+/// impl Relation for ChildOf {
+///     const ON_TARGET_KILLED: OnTargetKilled = OnTargetKilled::Kill;
+/// }
+/// ```
+pub trait Relation: 'static + Send + Sync {
+    const ON_TARGET_KILLED: OnTargetKilled = OnTargetKilled::Remove;
+}
+
+///
+/// Storage of every relation of type `R`. Relations are kept in one sparse set of sources
+/// per target, so both the targets of a source and the sources of a target are found without a scan.
+///
+pub struct Relations<R> {
+    by_target: HashMap<Entity, SparseSet<Entity, R>>,
+    // The targets of every source, in the order the relations were added.
+    by_source: SparseSet<Entity, Vec<Entity>>,
+}
+
+impl<R> Default for Relations<R> {
+    fn default() -> Self {
+        Relations {
+            by_target: HashMap::new(),
+            by_source: SparseSet::new(),
+        }
+    }
+}
+
+impl<R> Relations<R> {
+    ///
+    /// Adds a relation from `source` to `target`, returning the old value if they already were related.
+    ///
+    pub fn insert(&mut self, source: &Entity, value: R, target: &Entity) -> Option<R> {
+        let old = self.by_target.entry(*target).or_default().add(source, value);
+        if old.is_none() {
+            match self.by_source.get_mut(source) {
+                Some(targets) => targets.push(*target),
+                None => {
+                    self.by_source.add(source, vec![*target]);
+                },
+            }
+        }
+        old
+    }
+
+    /// Removes the relation from `source` to `target` and returns its value.
+    pub fn remove(&mut self, source: &Entity, target: &Entity) -> Option<R> {
+        let sources = self.by_target.get_mut(target)?;
+        let value = sources.remove(source)?;
+        if sources.is_empty() {
+            self.by_target.remove(target);
+        }
+        let targets = self.by_source.get_mut(source).unwrap();
+        targets.retain(|other| other != target);
+        if targets.is_empty() {
+            self.by_source.remove(source);
+        }
+        Some(value)
+    }
+
+    pub fn get(&self, source: &Entity, target: &Entity) -> Option<&R> {
+        self.by_target.get(target)?.get(source)
+    }
+
+    pub fn get_mut(&mut self, source: &Entity, target: &Entity) -> Option<&mut R> {
+        self.by_target.get_mut(target)?.get_mut(source)
+    }
+
+    pub fn contains(&self, source: &Entity, target: &Entity) -> bool {
+        self.get(source, target).is_some()
+    }
+
+    /// The targets `source` is related to, in the order the relations were added.
+    pub fn targets(&self, source: &Entity) -> &[Entity] {
+        self.by_source.get(source).map_or(&[], |targets| targets.as_slice())
+    }
+
+    /// Iterates the sources related to `target` together with the values of their relations.
+    pub fn sources(&self, target: &Entity) -> impl Iterator<Item = (Entity, &R)> {
+        self.by_target.get(target).into_iter()
+            .flat_map(|sources| (0..sources.len()).map(move |i| (*sources.key_at(i).unwrap(), sources.component_at(i).unwrap())))
+    }
+
+    /// Removes every relation of `source`.
+    pub(crate) fn remove_source(&mut self, source: &Entity) {
+        for target in self.targets(source).to_vec() {
+            self.remove(source, &target);
+        }
+    }
+
+    /// Removes every relation pointing at `target` and returns their sources.
+    pub(crate) fn remove_target(&mut self, target: &Entity) -> Vec<Entity> {
+        let sources: Vec<Entity> = self.sources(target).map(|(source, _)| source).collect();
+        for source in sources.iter() {
+            self.remove(source, target);
+        }
+        sources
+    }
+}
+
+impl Manager {
+    ///
+    /// Relates `source` to `target` with the given relation, replacing the value of an existing one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the comp_manager is borrowed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    /// struct Likes(u32);
+    ///
+    /// impl Relation for Likes {}
+    ///
+    /// let manager = Manager::new();
+    /// let alice = manager.add_entity();
+    /// let bob = manager.add_entity();
+    /// let carol = manager.add_entity();
+    ///
+    /// manager.add_relation(&alice, Likes(3), &bob);
+    /// manager.add_relation(&carol, Likes(5), &bob);
+    ///
+    /// assert_eq!(vec![bob], manager.targets_of::<Likes>(&alice));
+    /// assert_eq!(vec![alice, carol], manager.sources_of::<Likes>(&bob));
+    ///
+    /// manager.kill_entity(bob);
+    /// assert!(manager.targets_of::<Likes>(&alice).is_empty());
+    /// ```
+    pub fn add_relation<R: Relation>(&self, source: &Entity, relation: R, target: &Entity) -> Option<R> {
+        self.get_comp_manager_mut().add_relation(source, relation, target)
+    }
+
+    ///
+    /// Removes the relation from `source` to `target` and returns its value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the relations of type `R` are borrowed.
+    pub fn remove_relation<R: Relation>(&self, source: &Entity, target: &Entity) -> Option<R> {
+        self.get_comp_manager().relations_mut::<R>()?.remove(source, target)
+    }
+
+    ///
+    /// Returns true if `source` is related to `target`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the relations of type `R` are borrowed mutably.
+    pub fn has_relation<R: Relation>(&self, source: &Entity, target: &Entity) -> bool {
+        self.get_comp_manager().relations::<R>().is_some_and(|relations| relations.contains(source, target))
+    }
+
+    ///
+    /// Returns the targets `source` is related to, in the order the relations were added.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the relations of type `R` are borrowed mutably.
+    pub fn targets_of<R: Relation>(&self, source: &Entity) -> Vec<Entity> {
+        self.get_comp_manager().relations::<R>().map_or_else(Vec::new, |relations| relations.targets(source).to_vec())
+    }
+
+    ///
+    /// Returns the sources related to `target`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the relations of type `R` are borrowed mutably.
+    pub fn sources_of<R: Relation>(&self, target: &Entity) -> Vec<Entity> {
+        self.get_comp_manager().relations::<R>()
+            .map_or_else(Vec::new, |relations| relations.sources(target).map(|(source, _)| source).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Likes(u32);
+    struct ChildOf;
+    struct Targets;
+
+    impl Relation for Likes {}
+    impl Relation for ChildOf {
+        const ON_TARGET_KILLED: OnTargetKilled = OnTargetKilled::Kill;
+    }
+    impl Relation for Targets {
+        const ON_TARGET_KILLED: OnTargetKilled = OnTargetKilled::Dangle;
+    }
+
+    #[test]
+    fn storage() {
+        let e: Vec<Entity> = (0..4).map(|i| Entity::new(i, 0)).collect();
+        let mut relations = Relations::default();
+        assert!(relations.insert(&e[0], Likes(1), &e[1]).is_none());
+        relations.insert(&e[0], Likes(2), &e[2]);
+        relations.insert(&e[3], Likes(3), &e[1]);
+        assert_eq!(relations.insert(&e[0], Likes(4), &e[1]).unwrap().0, 1);
+
+        assert_eq!(relations.targets(&e[0]), &[e[1], e[2]]);
+        assert_eq!(relations.sources(&e[1]).map(|(s, l)| (s, l.0)).collect::<Vec<_>>(), vec![(e[0], 4), (e[3], 3)]);
+        relations.get_mut(&e[3], &e[1]).unwrap().0 = 5;
+        assert_eq!(relations.get(&e[3], &e[1]).unwrap().0, 5);
+
+        assert_eq!(relations.remove(&e[0], &e[1]).unwrap().0, 4);
+        assert!(relations.remove(&e[0], &e[1]).is_none());
+        assert_eq!(relations.targets(&e[0]), &[e[2]]);
+
+        assert_eq!(relations.remove_target(&e[1]), vec![e[3]]);
+        assert!(relations.targets(&e[3]).is_empty());
+        relations.remove_source(&e[0]);
+        assert_eq!(relations.sources(&e[2]).count(), 0);
+    }
+
+    #[test]
+    fn kill_policies() {
+        let manager = Manager::new();
+        let e: Vec<Entity> = (0..6).map(|_| manager.add_entity()).collect();
+        manager.add_relation(&e[1], Likes(0), &e[0]);
+        manager.add_relation(&e[0], Likes(0), &e[2]);
+        manager.add_relation(&e[3], ChildOf, &e[0]);
+        manager.add_relation(&e[4], ChildOf, &e[3]);
+        manager.add_relation(&e[5], Targets, &e[0]);
+
+        manager.kill_entity(e[0]);
+        let alive: Vec<bool> = e.iter().map(|e| manager.entity_alive(*e)).collect();
+        assert_eq!(alive, vec![false, true, true, false, false, true]);
+        assert!(manager.targets_of::<Likes>(&e[1]).is_empty());
+        assert!(manager.sources_of::<Likes>(&e[2]).is_empty());
+        assert!(manager.sources_of::<ChildOf>(&e[3]).is_empty());
+        assert_eq!(manager.targets_of::<Targets>(&e[5]), vec![e[0]]);
+        assert!(manager.has_relation::<Targets>(&e[5], &e[0]));
+
+        assert!(manager.remove_relation::<Targets>(&e[5], &e[0]).is_some());
+        assert!(!manager.has_relation::<Targets>(&e[5], &e[0]));
+    }
+}
@@ -0,0 +1,138 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+
+use super::cell::{AtomicRef, AtomicRefCell, AtomicRefMut, BorrowError};
+
+///
+/// Type erased store of resources, one value per type. Every resource has its own
+/// borrow flag, so different resources can be borrowed mutably at the same time.
+///
+#[derive(Default)]
+pub(crate) struct Resources {
+    resources: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+
+impl Resources {
+    pub fn insert<T: Send + Sync + 'static>(&mut self, value: T) -> Option<T> {
+        self.resources.insert(TypeId::of::<T>(), Box::new(AtomicRefCell::new(value)))
+            .map(|old| Self::unbox::<T>(old))
+    }
+
+    pub fn remove<T: Send + Sync + 'static>(&mut self) -> Option<T> {
+        self.resources.remove(&TypeId::of::<T>()).map(|old| Self::unbox::<T>(old))
+    }
+
+    pub fn contains<T: Send + Sync + 'static>(&self) -> bool {
+        self.resources.contains_key(&TypeId::of::<T>())
+    }
+
+    fn get<T: Send + Sync + 'static>(&self) -> Option<&AtomicRefCell<T>> {
+        self.resources.get(&TypeId::of::<T>()).and_then(|b| b.downcast_ref::<AtomicRefCell<T>>())
+    }
+
+    fn unbox<T: 'static>(resource: Box<dyn Any + Send + Sync>) -> T {
+        resource.downcast::<AtomicRefCell<T>>().unwrap().into_inner()
+    }
+}
+
+///
+/// Shared borrow of a resource, see [`Manager::resource`](crate::Manager::resource).
+/// Also usable as a system parameter, which panics if the resource is missing.
+///
+pub struct Res<'w, T> {
+    // Declared before the store guard so the resource borrow is released first.
+    value: AtomicRef<'w, T>,
+    _guard: AtomicRef<'w, Resources>,
+}
+
+impl<'w, T: Send + Sync + 'static> Res<'w, T> {
+    pub(crate) fn borrow(guard: AtomicRef<'w, Resources>) -> Result<Option<Self>, BorrowError> {
+        // Safety: the guard is stored next to the borrow and outlives it,
+        // so the store can't be changed while the reference is in use.
+        let resources = unsafe { &*(&*guard as *const Resources) };
+        match resources.get::<T>() {
+            Some(cell) => Ok(Some(Res { value: cell.try_borrow()?, _guard: guard })),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<T> std::ops::Deref for Res<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.value
+    }
+}
+
+///
+/// Mutable borrow of a resource, see [`Manager::resource_mut`](crate::Manager::resource_mut).
+/// Also usable as a system parameter, which panics if the resource is missing.
+///
+pub struct ResMut<'w, T> {
+    value: AtomicRefMut<'w, T>,
+    _guard: AtomicRef<'w, Resources>,
+}
+
+impl<'w, T: Send + Sync + 'static> ResMut<'w, T> {
+    pub(crate) fn borrow(guard: AtomicRef<'w, Resources>) -> Result<Option<Self>, BorrowError> {
+        // Safety: see Res::borrow.
+        let resources = unsafe { &*(&*guard as *const Resources) };
+        match resources.get::<T>() {
+            Some(cell) => Ok(Some(ResMut { value: cell.try_borrow_mut()?, _guard: guard })),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<T> std::ops::Deref for ResMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.value
+    }
+}
+
+impl<T> std::ops::DerefMut for ResMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Clock(u32);
+
+    #[test]
+    fn insert_remove() {
+        let mut resources = Resources::default();
+        assert!(resources.insert(Clock(1)).is_none());
+        assert_eq!(resources.insert(Clock(2)).unwrap().0, 1);
+        assert!(resources.contains::<Clock>());
+
+        assert_eq!(resources.remove::<Clock>().unwrap().0, 2);
+        assert!(resources.remove::<Clock>().is_none());
+        assert!(!resources.contains::<Clock>());
+    }
+
+    #[test]
+    fn borrows() {
+        let cell = AtomicRefCell::new(Resources::default());
+        cell.borrow_mut().insert(Clock(1));
+        cell.borrow_mut().insert(5u32);
+
+        let mut clock = ResMut::<Clock>::borrow(cell.borrow()).unwrap().unwrap();
+        let mut count = ResMut::<u32>::borrow(cell.borrow()).unwrap().unwrap();
+        clock.0 += 1;
+        *count += 1;
+
+        assert!(Res::<Clock>::borrow(cell.borrow()).is_err());
+        assert!(Res::<i64>::borrow(cell.borrow()).unwrap().is_none());
+        assert!(cell.try_borrow_mut().is_err());
+        drop(clock);
+
+        assert_eq!(Res::<Clock>::borrow(cell.borrow()).unwrap().unwrap().0, 2);
+    }
+}
@@ -0,0 +1,471 @@
+use std::any::TypeId;
+use std::convert::{TryFrom, TryInto};
+use std::io::{Read, Write};
+
+use crate::ecs::entity_handler::EntityHandler;
+use crate::sparse_set::SparseSet;
+use crate::{Component, ComponentManager, Entity, Manager, VersionOverflow};
+
+const MAGIC: [u8; 4] = *b"RECS";
+/// Version of the save format, bumped whenever the layout changes.
+pub const FORMAT_VERSION: u16 = 1;
+
+type EncodeFn = Box<dyn Fn(&ComponentManager, &mut Vec<u8>) + Send + Sync>;
+type DecodeFn = Box<dyn Fn(&mut Reader<'_>, &EntityHandler, u32) -> Result<DecodedSet, LoadError> + Send + Sync>;
+type InstallFn = Box<dyn FnOnce(&mut ComponentManager, &[TypeId])>;
+
+/// A decoded set waiting to be installed, with the keys at the front of its group.
+pub(crate) struct DecodedSet {
+    type_id: TypeId,
+    grouped: Vec<Entity>,
+    members: Vec<TypeId>,
+    install: InstallFn,
+}
+
+///
+/// Error returned when a world can't be loaded. The world is left untouched.
+///
+#[derive(Debug)]
+pub enum LoadError {
+    /// Reading the input failed.
+    Io(std::io::Error),
+    /// The input doesn't start like a saved world.
+    BadMagic,
+    /// The input was saved in a format version this build can't read.
+    UnsupportedVersion(u16),
+    /// The input ended in the middle of the world.
+    Truncated,
+    /// An entity doesn't fit into the entity ids of this build, or a component belongs to a dead entity.
+    InvalidEntity { index: u32, version: u32 },
+    /// A section holds components of a name without a registered codec.
+    UnknownComponent(String),
+    /// The codec of a component couldn't decode its bytes.
+    InvalidComponent { name: String, entity: Entity },
+    /// The input is inconsistent, like a broken free list or data after the last section.
+    Corrupt(&'static str),
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "failed to read world: {}", e),
+            LoadError::BadMagic => write!(f, "input is not a saved world"),
+            LoadError::UnsupportedVersion(version) => {
+                write!(f, "world was saved in format version {}, expected {}", version, FORMAT_VERSION)
+            },
+            LoadError::Truncated => write!(f, "saved world is truncated"),
+            LoadError::InvalidEntity { index, version } => {
+                write!(f, "invalid entity with index {} and version {}", index, version)
+            },
+            LoadError::UnknownComponent(name) => write!(f, "no codec registered for component {}", name),
+            LoadError::InvalidComponent { name, entity } => {
+                write!(f, "component {} of {:?} couldn't be decoded", name, entity)
+            },
+            LoadError::Corrupt(reason) => write!(f, "saved world is corrupt: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl From<std::io::Error> for LoadError {
+    fn from(e: std::io::Error) -> Self {
+        LoadError::Io(e)
+    }
+}
+
+///
+/// Encode and decode functions of a component type, saved in sections named after it.
+///
+pub(crate) struct ComponentCodec {
+    pub(crate) name: String,
+    pub(crate) type_id: TypeId,
+    encode: EncodeFn,
+    decode: DecodeFn,
+}
+
+/// Little endian reader over a saved world.
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], LoadError> {
+        if self.bytes.len() < len {
+            return Err(LoadError::Truncated);
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, LoadError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, LoadError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, LoadError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn len(&mut self) -> Result<usize, LoadError> {
+        let len = u64::from_le_bytes(self.take(8)?.try_into().unwrap());
+        usize::try_from(len).map_err(|_| LoadError::Corrupt("length out of range"))
+    }
+
+    fn entity(&mut self) -> Result<Entity, LoadError> {
+        let index = self.u32()?;
+        let version = self.u32()?;
+        Entity::try_new(index, version).ok_or(LoadError::InvalidEntity { index, version })
+    }
+
+    fn name(&mut self) -> Result<String, LoadError> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| LoadError::Corrupt("component name isn't utf-8"))
+    }
+}
+
+fn write_len(out: &mut Vec<u8>, len: usize) {
+    out.extend_from_slice(&(len as u64).to_le_bytes());
+}
+
+fn write_entity(out: &mut Vec<u8>, entity: &Entity) {
+    out.extend_from_slice(&entity.get_index().to_le_bytes());
+    out.extend_from_slice(&entity.get_version().to_le_bytes());
+}
+
+fn write_entities(out: &mut Vec<u8>, entities: &EntityHandler) {
+    out.push(match entities.overflow() {
+        VersionOverflow::Retire => 0,
+        VersionOverflow::Wrap => 1,
+    });
+    let (head_index, killed) = entities.free_list();
+    out.extend_from_slice(&head_index.to_le_bytes());
+    write_len(out, killed);
+    write_len(out, entities.slots().len());
+    for entity in entities.slots() {
+        write_entity(out, entity);
+    }
+}
+
+fn read_entities(reader: &mut Reader<'_>) -> Result<EntityHandler, LoadError> {
+    let overflow = match reader.u8()? {
+        0 => VersionOverflow::Retire,
+        1 => VersionOverflow::Wrap,
+        _ => return Err(LoadError::Corrupt("unknown version overflow policy")),
+    };
+    let head_index = reader.u32()?;
+    let killed = reader.len()?;
+    let len = reader.len()?;
+    // Every slot takes 8 bytes, so a bogus length can't reserve more than the input.
+    let mut entities = Vec::with_capacity(len.min(reader.bytes.len() / 8));
+    for _ in 0..len {
+        entities.push(reader.entity()?);
+    }
+    EntityHandler::from_parts(entities, head_index, killed, overflow).ok_or(LoadError::Corrupt("entity free list"))
+}
+
+/// Checks that the loaded members of every full group have the same entities at the front of their sets.
+fn check_groups(decoded: &[DecodedSet]) -> Result<(), LoadError> {
+    for set in decoded {
+        let mismatch = decoded.iter()
+            .filter(|other| set.members.contains(&other.type_id))
+            .any(|other| other.grouped != set.grouped);
+        if mismatch {
+            return Err(LoadError::Corrupt("members of a group disagree"));
+        }
+    }
+    Ok(())
+}
+
+impl ComponentManager {
+    ///
+    /// Registers the functions saving and loading components of type `T` under a stable name,
+    /// see [`Manager::save_to`]. `decode` gets exactly the bytes `encode` wrote for one component
+    /// and returns None if they are invalid.
+    ///
+    /// # Panics
+    ///
+    /// Panics if another component type is registered under the same name.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    /// use std::convert::TryInto;
+    /// struct Hp(u32);
+    ///
+    /// register_components!(Hp);
+    ///
+    /// let manager = Manager::new();
+    /// manager.get_comp_manager_mut().register_codec::<Hp, _, _>(
+    ///     "hp",
+    ///     |hp, out| out.extend_from_slice(&hp.0.to_le_bytes()),
+    ///     |bytes| Some(Hp(u32::from_le_bytes(bytes.try_into().ok()?))),
+    /// );
+    /// ```
+    pub fn register_codec<T, E, D>(&mut self, name: &str, encode: E, decode: D)
+    where
+        T: Component,
+        E: Fn(&T, &mut Vec<u8>) + Send + Sync + 'static,
+        D: Fn(&[u8]) -> Option<T> + Send + Sync + 'static,
+    {
+        let section = name.to_string();
+        self.add_codec(ComponentCodec {
+            name: name.to_string(),
+            type_id: TypeId::of::<T>(),
+            encode: Box::new(move |cm: &ComponentManager, out: &mut Vec<u8>| {
+                let set = cm.get_components::<T>();
+                let (group_len, len) = set.as_ref().map_or((0, 0), |set| (set.group_len(), set.len()));
+                write_len(out, group_len);
+                write_len(out, len);
+                let set = match set {
+                    Some(set) => set,
+                    None => return,
+                };
+                for i in 0..len {
+                    write_entity(out, set.key_at(i).unwrap());
+                    let start = out.len();
+                    out.extend_from_slice(&[0; 4]);
+                    encode(set.component_at(i).unwrap(), out);
+                    let len = (out.len() - start - 4) as u32;
+                    out[start..start + 4].copy_from_slice(&len.to_le_bytes());
+                }
+            }),
+            decode: Box::new(move |reader: &mut Reader<'_>, entities: &EntityHandler, tick: u32| {
+                let group_len = reader.len()?;
+                let len = reader.len()?;
+                if group_len > len {
+                    return Err(LoadError::Corrupt("group is longer than its set"));
+                }
+                let mut set = SparseSet::<Entity, T>::new();
+                set.set_change_tick(tick);
+                for _ in 0..len {
+                    let entity = reader.entity()?;
+                    if !entities.is_alive(entity) {
+                        return Err(LoadError::InvalidEntity { index: entity.get_index(), version: entity.get_version() });
+                    }
+                    let size = reader.u32()? as usize;
+                    let component = decode(reader.take(size)?)
+                        .ok_or_else(|| LoadError::InvalidComponent { name: section.clone(), entity })?;
+                    if set.add(&entity, component).is_some() {
+                        return Err(LoadError::Corrupt("entity has two components of the same type"));
+                    }
+                }
+                // Entries are saved in packed order, so grouping the front restores the group boundary.
+                let grouped: Vec<Entity> = (0..group_len).map(|i| *set.key_at(i).unwrap()).collect();
+                for entity in grouped.iter() {
+                    set.group(entity);
+                }
+                Ok(DecodedSet {
+                    type_id: TypeId::of::<T>(),
+                    grouped,
+                    members: T::group_members(),
+                    install: Box::new(move |cm: &mut ComponentManager, cleared: &[TypeId]| cm.replace_components(set, cleared)),
+                })
+            }),
+        });
+    }
+}
+
+impl Manager {
+    ///
+    /// Saves the entities and every component type with a registered codec in a versioned binary format.
+    /// Component sections are keyed by the names given to [`ComponentManager::register_codec`], so a world
+    /// can be loaded by another build as long as it registers the same names.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any component with a codec is borrowed mutably.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    /// use std::convert::TryInto;
+    /// struct Hp(u32);
+    ///
+    /// register_components!(Hp);
+    ///
+    /// let manager = Manager::new();
+    /// manager.get_comp_manager_mut().register_codec::<Hp, _, _>(
+    ///     "hp",
+    ///     |hp, out| out.extend_from_slice(&hp.0.to_le_bytes()),
+    ///     |bytes| Some(Hp(u32::from_le_bytes(bytes.try_into().ok()?))),
+    /// );
+    /// let entity = manager.add_entity();
+    /// manager.add_component(&entity, Hp(10));
+    ///
+    /// let mut save = Vec::new();
+    /// manager.save_to(&mut save).unwrap();
+    ///
+    /// manager.kill_entity(entity);
+    /// manager.load_from(save.as_slice()).unwrap();
+    /// assert_eq!(10, manager.get_comp_manager().get_components::<Hp>().unwrap().get(&entity).unwrap().0);
+    /// ```
+    pub fn save_to<W: Write>(&self, mut writer: W) -> std::io::Result<()> {
+        let comp_manager = self.get_comp_manager();
+        let mut out = Vec::new();
+        out.extend_from_slice(&MAGIC);
+        out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        write_entities(&mut out, &self.ent_handler.lock().unwrap());
+
+        out.extend_from_slice(&(comp_manager.codecs().len() as u32).to_le_bytes());
+        for codec in comp_manager.codecs() {
+            out.extend_from_slice(&(codec.name.len() as u32).to_le_bytes());
+            out.extend_from_slice(codec.name.as_bytes());
+            (codec.encode)(&comp_manager, &mut out);
+        }
+        writer.write_all(&out)
+    }
+
+    ///
+    /// Replaces the world with one saved by [`Manager::save_to`], with identical entity ids, versions
+    /// and packed order. Components of types not in the save are cleared like in [`Manager::restore`].
+    /// The whole input is checked before anything is replaced, so on error the world is left untouched.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the comp_manager is borrowed.
+    pub fn load_from<R: Read>(&self, mut reader: R) -> Result<(), LoadError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let mut reader = Reader { bytes: &bytes };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(LoadError::BadMagic);
+        }
+        let version = reader.u16()?;
+        if version != FORMAT_VERSION {
+            return Err(LoadError::UnsupportedVersion(version));
+        }
+        let entities = read_entities(&mut reader)?;
+
+        let mut comp_manager = self.get_comp_manager_mut();
+        let tick = comp_manager.change_tick();
+        let mut kept = Vec::new();
+        let mut decoded = Vec::new();
+        for _ in 0..reader.u32()? {
+            let name = reader.name()?;
+            let codec = comp_manager.codec(&name).ok_or(LoadError::UnknownComponent(name))?;
+            if kept.contains(&codec.type_id) {
+                return Err(LoadError::Corrupt("component type saved twice"));
+            }
+            decoded.push((codec.decode)(&mut reader, &entities, tick)?);
+            kept.push(codec.type_id);
+        }
+        if !reader.bytes.is_empty() {
+            return Err(LoadError::Corrupt("data after the last section"));
+        }
+        check_groups(&decoded)?;
+
+        *self.ent_handler.lock().unwrap() = entities;
+        let cleared = comp_manager.clear_components(&kept);
+        for set in decoded {
+            (set.install)(&mut comp_manager, &cleared);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Pos(i32);
+    struct Vel(i32);
+    struct Name(String);
+
+    group!(Pos, Vel);
+    register_components!(Name);
+
+    fn register(manager: &Manager) {
+        let mut cm = manager.get_comp_manager_mut();
+        cm.register_codec::<Pos, _, _>("pos", |pos, out| out.extend_from_slice(&pos.0.to_le_bytes()),
+            |bytes| Some(Pos(i32::from_le_bytes(bytes.try_into().ok()?))));
+        cm.register_codec::<Vel, _, _>("vel", |vel, out| out.extend_from_slice(&vel.0.to_le_bytes()),
+            |bytes| Some(Vel(i32::from_le_bytes(bytes.try_into().ok()?))));
+        cm.register_codec::<Name, _, _>("name", |name, out| out.extend_from_slice(name.0.as_bytes()),
+            |bytes| String::from_utf8(bytes.to_vec()).ok().map(Name));
+    }
+
+    fn world() -> (Manager, Vec<Entity>) {
+        let manager = Manager::new();
+        register(&manager);
+        let e: Vec<Entity> = (0..5).map(|_| manager.add_entity()).collect();
+        for (i, entity) in e.iter().enumerate() {
+            manager.add_component(entity, Pos(i as i32));
+            if i != 1 {
+                manager.add_component(entity, Vel(i as i32 * 10));
+            }
+        }
+        manager.add_component(&e[3], Name("ship".to_string()));
+        manager.kill_entity(e[0]);
+        (manager, e)
+    }
+
+    fn save(manager: &Manager) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        manager.save_to(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trip() {
+        let (manager, e) = world();
+        let bytes = save(&manager);
+
+        let loaded = Manager::new();
+        register(&loaded);
+        loaded.load_from(bytes.as_slice()).unwrap();
+        assert_eq!(save(&loaded), bytes);
+
+        assert!(!loaded.entity_alive(e[0]) && loaded.entity_alive(e[4]));
+        assert_eq!(loaded.add_entity(), Entity::new(0, 1));
+        let cm = loaded.get_comp_manager();
+        assert_eq!(cm.group_len::<Pos>(), 3);
+        assert_eq!(cm.get_components::<Vel>().unwrap().get(&e[2]).unwrap().0, 20);
+        assert_eq!(cm.get_components::<Name>().unwrap().get(&e[3]).unwrap().0, "ship");
+    }
+
+    #[test]
+    fn load_errors() {
+        let (manager, _) = world();
+        let bytes = save(&manager);
+
+        for len in [0, 5, 20, bytes.len() - 1] {
+            assert!(matches!(manager.load_from(&bytes[..len]), Err(LoadError::Truncated)));
+        }
+        let mut bad = bytes.clone();
+        bad[0] = b'X';
+        assert!(matches!(manager.load_from(bad.as_slice()), Err(LoadError::BadMagic)));
+        let mut bad = bytes.clone();
+        bad[4] = 2;
+        assert!(matches!(manager.load_from(bad.as_slice()), Err(LoadError::UnsupportedVersion(2))));
+        let mut bad = bytes.clone();
+        bad.push(0);
+        assert!(matches!(manager.load_from(bad.as_slice()), Err(LoadError::Corrupt(_))));
+
+        // The vel section starts with its group length, its length and entries of 16 bytes.
+        let vel = bytes.windows(3).position(|name| name == b"vel").unwrap() + 3;
+        let mut bad = bytes.clone();
+        bad[vel] = 2;
+        assert!(matches!(manager.load_from(bad.as_slice()), Err(LoadError::Corrupt("members of a group disagree"))));
+        let mut bad = bytes.clone();
+        let entries = vel + 16;
+        bad[entries..entries + 32].rotate_left(16);
+        assert!(matches!(manager.load_from(bad.as_slice()), Err(LoadError::Corrupt("members of a group disagree"))));
+        manager.load_from(bytes.as_slice()).unwrap();
+
+        let other = Manager::new();
+        other.get_comp_manager_mut().register_codec::<Pos, _, _>("pos", |_, _| {}, |_| Some(Pos(0)));
+        assert!(matches!(other.load_from(bytes.as_slice()), Err(LoadError::UnknownComponent(_))));
+        other.get_comp_manager_mut().register_codec::<Vel, _, _>("vel", |_, _| {}, |_| None);
+        other.get_comp_manager_mut().register_codec::<Name, _, _>("name", |_, _| {}, |_| None);
+        assert!(matches!(other.load_from(bytes.as_slice()), Err(LoadError::InvalidComponent { .. })));
+        assert_eq!(other.get_comp_manager().group_len::<Pos>(), 0);
+        assert!(!other.entity_alive(Entity::new(1, 0)));
+    }
+}
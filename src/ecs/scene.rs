@@ -0,0 +1,516 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::{Component, ComponentManager, Entity, Manager};
+
+type ConstructFn = Arc<dyn Fn(&Fields<'_>, Entity) -> Result<InsertFn, SceneError> + Send + Sync>;
+type InsertFn = Box<dyn FnOnce(&mut ComponentManager)>;
+
+///
+/// Error returned when prefabs can't be loaded or instantiated.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum SceneError {
+    /// A line of the scene text is invalid.
+    Parse { line: usize, message: String },
+    /// No prefab of that name was loaded.
+    UnknownPrefab(String),
+    /// A field of a component is missing or has the wrong type.
+    Field { component: String, field: String, message: String },
+}
+
+impl std::fmt::Display for SceneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SceneError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            SceneError::UnknownPrefab(name) => write!(f, "unknown prefab {}", name),
+            SceneError::Field { component, field, message } => {
+                write!(f, "field {} of component {}: {}", field, component, message)
+            },
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+///
+/// Value of a component field in a scene.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Str(String),
+    /// Reference to another entity of the same prefab, written `@name`.
+    Entity(String),
+}
+
+impl Value {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Int(_) => "an integer",
+            Value::Float(_) => "a float",
+            Value::Bool(_) => "a bool",
+            Value::Str(_) => "a string",
+            Value::Entity(_) => "an entity",
+        }
+    }
+}
+
+///
+/// The fields of a component in a prefab, handed to the constructor registered
+/// with [`Manager::register_prefab_component`].
+///
+pub struct Fields<'a> {
+    component: &'a str,
+    fields: &'a [(String, Value)],
+    entities: &'a HashMap<&'a str, Entity>,
+}
+
+impl<'a> Fields<'a> {
+    /// Gets the value of a field, or None if the prefab doesn't set it.
+    pub fn get(&self, field: &str) -> Option<&'a Value> {
+        self.fields.iter().find(|(name, _)| name == field).map(|(_, value)| value)
+    }
+
+    /// Creates an error for a field of this component, for checks done by the constructor.
+    pub fn error(&self, field: &str, message: impl Into<String>) -> SceneError {
+        SceneError::Field { component: self.component.to_string(), field: field.to_string(), message: message.into() }
+    }
+
+    fn require(&self, field: &str, expected: &str) -> Result<&'a Value, SceneError> {
+        self.get(field).ok_or_else(|| self.error(field, format!("missing, expected {}", expected)))
+    }
+
+    fn mismatch(&self, field: &str, expected: &str, value: &Value) -> SceneError {
+        self.error(field, format!("expected {}, found {}", expected, value.type_name()))
+    }
+
+    pub fn int(&self, field: &str) -> Result<i64, SceneError> {
+        match self.require(field, "an integer")? {
+            Value::Int(value) => Ok(*value),
+            value => Err(self.mismatch(field, "an integer", value)),
+        }
+    }
+
+    /// Gets a float field. Integers are accepted as well.
+    pub fn float(&self, field: &str) -> Result<f64, SceneError> {
+        match self.require(field, "a float")? {
+            Value::Float(value) => Ok(*value),
+            Value::Int(value) => Ok(*value as f64),
+            value => Err(self.mismatch(field, "a float", value)),
+        }
+    }
+
+    pub fn bool(&self, field: &str) -> Result<bool, SceneError> {
+        match self.require(field, "a bool")? {
+            Value::Bool(value) => Ok(*value),
+            value => Err(self.mismatch(field, "a bool", value)),
+        }
+    }
+
+    pub fn str(&self, field: &str) -> Result<&'a str, SceneError> {
+        match self.require(field, "a string")? {
+            Value::Str(value) => Ok(value),
+            value => Err(self.mismatch(field, "a string", value)),
+        }
+    }
+
+    /// Gets an entity field, remapped to the entity spawned for the referenced prefab entity.
+    pub fn entity(&self, field: &str) -> Result<Entity, SceneError> {
+        match self.require(field, "an entity")? {
+            Value::Entity(name) => Ok(self.entities[name.as_str()]),
+            value => Err(self.mismatch(field, "an entity", value)),
+        }
+    }
+}
+
+struct ComponentDef {
+    line: usize,
+    name: String,
+    fields: Vec<(String, Value)>,
+}
+
+struct EntityDef {
+    name: Option<String>,
+    components: Vec<ComponentDef>,
+}
+
+struct Prefab {
+    entities: Vec<EntityDef>,
+}
+
+///
+/// Registered component constructors and loaded prefabs of a manager.
+///
+#[derive(Default)]
+pub(crate) struct Prefabs {
+    constructors: HashMap<String, ConstructFn>,
+    // Shared so prefabs can be instantiated without holding the lock.
+    prefabs: HashMap<String, Arc<Prefab>>,
+}
+
+/// Splits a line into whitespace separated tokens, keeping quoted strings together and dropping comments.
+fn tokenize(line: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut chars = line.chars();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                token.push(c);
+            },
+            '\\' if quoted => {
+                token.push(c);
+                token.push(chars.next().ok_or("unterminated string")?);
+            },
+            '#' if !quoted => break,
+            c if c.is_whitespace() && !quoted => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+            },
+            c => token.push(c),
+        }
+    }
+    if quoted {
+        return Err("unterminated string".to_string());
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+fn parse_value(text: &str) -> Result<Value, String> {
+    if let Some(name) = text.strip_prefix('@') {
+        if name.is_empty() {
+            return Err("missing entity name after @".to_string());
+        }
+        return Ok(Value::Entity(name.to_string()));
+    }
+    if let Some(quoted) = text.strip_prefix('"') {
+        let quoted = quoted.strip_suffix('"').ok_or_else(|| format!("invalid string {}", text))?;
+        let mut value = String::new();
+        let mut chars = quoted.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    Some(c @ ('"' | '\\')) => value.push(c),
+                    _ => return Err(format!("invalid escape in {}", text)),
+                },
+                '"' => return Err(format!("invalid string {}", text)),
+                c => value.push(c),
+            }
+        }
+        return Ok(Value::Str(value));
+    }
+    match text {
+        "true" => Ok(Value::Bool(true)),
+        "false" => Ok(Value::Bool(false)),
+        _ => text.parse::<i64>().map(Value::Int)
+            .or_else(|_| text.parse::<f64>().map(Value::Float))
+            .map_err(|_| format!("invalid value {}", text)),
+    }
+}
+
+/// Checks that every entity reference of a prefab points at one of its named entities.
+fn check_references(prefab: &Prefab) -> Result<(), SceneError> {
+    let names: HashSet<&str> = prefab.entities.iter().filter_map(|entity| entity.name.as_deref()).collect();
+    for component in prefab.entities.iter().flat_map(|entity| entity.components.iter()) {
+        for (_, value) in component.fields.iter() {
+            if let Value::Entity(name) = value {
+                if !names.contains(name.as_str()) {
+                    let message = format!("unknown entity @{}", name);
+                    return Err(SceneError::Parse { line: component.line, message });
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+impl Prefabs {
+    fn parse(&self, source: &str) -> Result<Vec<(String, Prefab)>, SceneError> {
+        let mut prefabs: Vec<(String, Prefab)> = Vec::new();
+        for (index, text) in source.lines().enumerate() {
+            let line = index + 1;
+            let error = |message: String| SceneError::Parse { line, message };
+            let tokens = tokenize(text).map_err(error)?;
+            let (keyword, args) = match tokens.split_first() {
+                Some(split) => split,
+                None => continue,
+            };
+            match keyword.as_str() {
+                "prefab" => {
+                    if args.len() != 1 {
+                        return Err(error("expected `prefab <name>`".to_string()));
+                    }
+                    if prefabs.iter().any(|(name, _)| *name == args[0]) {
+                        return Err(error(format!("duplicate prefab {}", args[0])));
+                    }
+                    prefabs.push((args[0].clone(), Prefab { entities: Vec::new() }));
+                },
+                "entity" => {
+                    let (_, prefab) = prefabs.last_mut().ok_or_else(|| error("entity outside of a prefab".to_string()))?;
+                    if args.len() > 1 {
+                        return Err(error("expected `entity [name]`".to_string()));
+                    }
+                    let name = args.first().cloned();
+                    if name.is_some() && prefab.entities.iter().any(|entity| entity.name == name) {
+                        return Err(error(format!("duplicate entity {}", args[0])));
+                    }
+                    prefab.entities.push(EntityDef { name, components: Vec::new() });
+                },
+                component => {
+                    let entity = prefabs.last_mut().and_then(|(_, prefab)| prefab.entities.last_mut())
+                        .ok_or_else(|| error(format!("component {} outside of an entity", component)))?;
+                    if !self.constructors.contains_key(component) {
+                        return Err(error(format!("unknown component {}", component)));
+                    }
+                    if entity.components.iter().any(|other| other.name == component) {
+                        return Err(error(format!("duplicate component {}", component)));
+                    }
+                    let mut fields: Vec<(String, Value)> = Vec::new();
+                    for arg in args {
+                        let (field, value) = arg.split_once('=').ok_or_else(|| error(format!("expected `field=value`, found {}", arg)))?;
+                        if fields.iter().any(|(name, _)| name == field) {
+                            return Err(error(format!("duplicate field {}", field)));
+                        }
+                        fields.push((field.to_string(), parse_value(value).map_err(error)?));
+                    }
+                    entity.components.push(ComponentDef { line, name: component.to_string(), fields });
+                },
+            }
+        }
+        for (_, prefab) in prefabs.iter() {
+            check_references(prefab)?;
+        }
+        Ok(prefabs)
+    }
+}
+
+impl Manager {
+    ///
+    /// Registers the constructor of components named `name` in scene text, see [`Manager::load_prefabs`].
+    /// Registering a name again replaces its constructor.
+    ///
+    pub fn register_prefab_component<T, F>(&self, name: &str, construct: F)
+    where
+        T: Component,
+        F: Fn(&Fields<'_>) -> Result<T, SceneError> + Send + Sync + 'static,
+    {
+        let construct: ConstructFn = Arc::new(move |fields: &Fields<'_>, entity: Entity| {
+            let component = construct(fields)?;
+            Ok(Box::new(move |cm: &mut ComponentManager| cm.add_component(&entity, component)) as InsertFn)
+        });
+        self.prefabs.write().unwrap().constructors.insert(name.to_string(), construct);
+    }
+
+    ///
+    /// Loads prefabs from scene text, replacing loaded prefabs of the same names. Nothing is loaded
+    /// if the text is invalid or uses components without a registered constructor.
+    ///
+    /// A prefab starts with `prefab <name>` and holds entities, each starting with `entity [name]`.
+    /// Every other line adds a component to the last entity, written as its registered name followed
+    /// by `field=value` pairs. Values are integers, floats, `true`, `false`, quoted strings or `@name`
+    /// references to named entities of the same prefab. Everything after a `#` is a comment.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    /// struct Pos(f64, f64);
+    /// struct Mount(Entity);
+    ///
+    /// register_components!(Pos, Mount);
+    ///
+    /// let manager = Manager::new();
+    /// manager.register_prefab_component("Pos", |fields| Ok(Pos(fields.float("x")?, fields.float("y")?)));
+    /// manager.register_prefab_component("Mount", |fields| Ok(Mount(fields.entity("on")?)));
+    ///
+    /// manager.load_prefabs(r#"
+    ///     prefab ship
+    ///     entity hull
+    ///         Pos x=0 y=0
+    ///     entity turret
+    ///         Pos x=1.5 y=0
+    ///         Mount on=@hull   # remapped to the spawned hull
+    /// "#).unwrap();
+    ///
+    /// let ship = manager.instantiate_prefab("ship").unwrap();
+    /// let mounts = manager.get_comp_manager().get_components::<Mount>().unwrap().get(&ship[1]).map(|m| m.0);
+    /// assert_eq!(Some(ship[0]), mounts);
+    /// ```
+    pub fn load_prefabs(&self, source: &str) -> Result<(), SceneError> {
+        let mut registry = self.prefabs.write().unwrap();
+        let prefabs = registry.parse(source)?;
+        registry.prefabs.extend(prefabs.into_iter().map(|(name, prefab)| (name, Arc::new(prefab))));
+        Ok(())
+    }
+
+    ///
+    /// Spawns every entity of a prefab and returns them in the order they are written.
+    /// Entity fields are remapped to the spawned entities. If a constructor fails,
+    /// the spawned entities are killed again and its error is returned. The constructors
+    /// run without locking the prefabs, so they may register components or load prefabs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the comp_manager is borrowed.
+    pub fn instantiate_prefab(&self, name: &str) -> Result<Vec<Entity>, SceneError> {
+        let registry = self.prefabs.read().unwrap();
+        let prefab = Arc::clone(registry.prefabs.get(name).ok_or_else(|| SceneError::UnknownPrefab(name.to_string()))?);
+        // Constructors are checked when loading, and can only be replaced afterwards.
+        let constructors: HashMap<&str, ConstructFn> = prefab.entities.iter()
+            .flat_map(|def| def.components.iter())
+            .map(|component| (component.name.as_str(), Arc::clone(&registry.constructors[&component.name])))
+            .collect();
+        drop(registry);
+
+        let entities: Vec<Entity> = prefab.entities.iter().map(|_| self.add_entity()).collect();
+        let names: HashMap<&str, Entity> = prefab.entities.iter().zip(entities.iter())
+            .filter_map(|(def, entity)| def.name.as_deref().map(|name| (name, *entity)))
+            .collect();
+
+        let mut inserts = Vec::new();
+        for (def, entity) in prefab.entities.iter().zip(entities.iter()) {
+            for component in def.components.iter() {
+                let fields = Fields { component: &component.name, fields: &component.fields, entities: &names };
+                let construct = &constructors[component.name.as_str()];
+                match construct(&fields, *entity) {
+                    Ok(insert) => inserts.push(insert),
+                    Err(e) => {
+                        entities.iter().for_each(|entity| self.kill_entity(*entity));
+                        return Err(e);
+                    },
+                }
+            }
+        }
+
+        let mut comp_manager = self.get_comp_manager_mut();
+        for insert in inserts {
+            insert(&mut comp_manager);
+        }
+        Ok(entities)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Pos(i64, i64);
+    struct Vel(i64, i64);
+    struct Name(String);
+    struct Target(Entity, bool);
+
+    group!(Pos, Vel);
+    register_components!(Name, Target);
+
+    fn manager() -> Manager {
+        let manager = Manager::new();
+        manager.register_prefab_component("Pos", |f| Ok(Pos(f.int("x")?, f.int("y")?)));
+        manager.register_prefab_component("Vel", |f| Ok(Vel(f.int("x")?, f.int("y")?)));
+        manager.register_prefab_component("Name", |f| Ok(Name(f.str("value")?.to_string())));
+        manager.register_prefab_component("Target", |f| {
+            let locked = f.get("locked").map_or(Ok(false), |_| f.bool("locked"))?;
+            Ok(Target(f.entity("entity")?, locked))
+        });
+        manager
+    }
+
+    const SCENE: &str = r#"
+        # Two ships targeting each other.
+        prefab duel
+        entity red
+            Pos x=0 y=0
+            Vel x=1 y=0
+            Name value="Red \"One\""
+            Target entity=@blue locked=true
+        entity blue
+            Pos x=10 y=0
+            Target entity=@red
+        entity   # anonymous
+            Name value="debris # not a comment"
+    "#;
+
+    #[test]
+    fn instantiate() {
+        let manager = manager();
+        manager.load_prefabs(SCENE).unwrap();
+        manager.add_entity();
+        let first = manager.instantiate_prefab("duel").unwrap();
+        let second = manager.instantiate_prefab("duel").unwrap();
+        assert_eq!(first.len(), 3);
+
+        let cm = manager.get_comp_manager();
+        let targets = cm.get_components::<Target>().unwrap();
+        let names = cm.get_components::<Name>().unwrap();
+        for e in [&first, &second] {
+            assert_eq!(targets.get(&e[0]).map(|t| (t.0, t.1)), Some((e[1], true)));
+            assert_eq!(targets.get(&e[1]).map(|t| (t.0, t.1)), Some((e[0], false)));
+            assert_eq!(names.get(&e[0]).unwrap().0, "Red \"One\"");
+            assert_eq!(names.get(&e[2]).unwrap().0, "debris # not a comment");
+        }
+        assert_eq!(cm.group_len::<Pos>(), 2);
+        let pos = cm.get_components::<Pos>().unwrap().get(&second[1]).map(|pos| (pos.0, pos.1));
+        assert_eq!(pos, Some((10, 0)));
+        let vel = cm.get_components::<Vel>().unwrap().get(&first[0]).map(|vel| (vel.0, vel.1));
+        assert_eq!(vel, Some((1, 0)));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let manager = manager();
+        let line = |source: &str| match manager.load_prefabs(source) {
+            Err(SceneError::Parse { line, .. }) => line,
+            other => panic!("expected a parse error, got {:?}", other),
+        };
+        assert_eq!(line("entity a"), 1);
+        assert_eq!(line("prefab p\nPos x=1 y=2"), 2);
+        assert_eq!(line("prefab p\nentity\nHealth value=1"), 3);
+        assert_eq!(line("prefab p\nentity\nPos x=1 x=2"), 3);
+        assert_eq!(line("prefab p\nentity\nName value=\"open"), 3);
+        assert_eq!(line("prefab p\nentity\nPos x=1 y=two"), 3);
+        assert_eq!(line("prefab p\nentity a\nentity a"), 3);
+        assert_eq!(line("prefab p\nentity a\nTarget entity=@b\nprefab q\nentity b"), 3);
+        assert_eq!(manager.instantiate_prefab("p"), Err(SceneError::UnknownPrefab("p".to_string())));
+    }
+
+    #[test]
+    fn field_errors() {
+        let manager = manager();
+        manager.load_prefabs("prefab bad\nentity\nName value=\"ok\"\nentity\nPos x=1 y=\"2\"").unwrap();
+        let error = manager.instantiate_prefab("bad").unwrap_err();
+        assert_eq!(error, SceneError::Field {
+            component: "Pos".to_string(),
+            field: "y".to_string(),
+            message: "expected an integer, found a string".to_string(),
+        });
+        assert_eq!(manager.query::<(&Name,)>().iter().count(), 0);
+        assert!(!manager.entity_alive(Entity::new(0, 0)));
+    }
+
+    #[test]
+    fn constructor_loads_prefabs() {
+        let manager = Arc::new(manager());
+        let weak = Arc::downgrade(&manager);
+        manager.register_prefab_component("Spawner", move |_| {
+            let manager = weak.upgrade().unwrap();
+            manager.register_prefab_component("Vel", |_| Ok(Vel(0, 0)));
+            manager.load_prefabs("prefab late\nentity\nVel").unwrap();
+            Ok(Pos(0, 0))
+        });
+        manager.load_prefabs("prefab spawner\nentity\nSpawner").unwrap();
+
+        assert_eq!(manager.instantiate_prefab("spawner").unwrap().len(), 1);
+        assert_eq!(manager.instantiate_prefab("late").unwrap().len(), 1);
+        assert_eq!(manager.query::<(&Vel,)>().iter().count(), 1);
+    }
+}
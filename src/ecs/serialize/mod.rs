@@ -0,0 +1,262 @@
+use std::any::TypeId;
+
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{self, Serialize, SerializeStruct, Serializer};
+
+use crate::sparse_set::{SparseKey, SparseSet};
+use crate::ecs::ComponentView;
+use crate::ecs::cm::View;
+use crate::{Component, ComponentManager, Entity, Manager};
+
+type SerializeFn = for<'a> fn(&'a ComponentManager) -> Box<dyn erased_serde::Serialize + 'a>;
+
+/// Serializes the entity as an `(index, version)` tuple.
+impl Serialize for Entity {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (self.get_index(), self.get_version()).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Entity {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (index, version) = <(u32, u32)>::deserialize(deserializer)?;
+        Entity::try_new(index, version)
+            .ok_or_else(|| de::Error::custom(format_args!("entity ({}, {}) is out of range", index, version)))
+    }
+}
+
+struct Entries<'a, K, V>(&'a SparseSet<K, V>);
+
+impl<K: SparseKey + Serialize, V: Serialize> Serialize for Entries<'_, K, V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let set = self.0;
+        serializer.collect_seq((0..set.len()).map(|i| (set.key_at(i).unwrap(), set.component_at(i).unwrap())))
+    }
+}
+
+///
+/// Serializes the set as its group length and its `(key, value)` entries in packed order.
+/// Change ticks are not serialized.
+///
+impl<K: SparseKey + Serialize, V: Serialize> Serialize for SparseSet<K, V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("SparseSet", 2)?;
+        state.serialize_field("group_len", &self.group_len())?;
+        state.serialize_field("entries", &Entries(self))?;
+        state.end()
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename = "SparseSet")]
+struct SparseSetData<K, V> {
+    group_len: usize,
+    entries: Vec<(K, V)>,
+}
+
+impl<'de, K: SparseKey + Deserialize<'de>, V: Deserialize<'de>> Deserialize<'de> for SparseSet<K, V> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = SparseSetData::<K, V>::deserialize(deserializer)?;
+        let mut set = SparseSet::new();
+        set.reserve(data.entries.len());
+        for (key, value) in data.entries {
+            if set.contains(&key) || set.stale_key(&key).is_some() {
+                return Err(de::Error::custom(format_args!("duplicate sparse index of key {:?}", key)));
+            }
+            set.add(&key, value);
+        }
+        if data.group_len > set.len() {
+            return Err(de::Error::custom("group is longer than the set"));
+        }
+        // Entries are in packed order, so grouping the front restores the group boundary.
+        for i in 0..data.group_len {
+            let key = *set.key_at(i).unwrap();
+            set.group(&key);
+        }
+        Ok(set)
+    }
+}
+
+///
+/// Serializes the set of a component type under its registered name.
+///
+pub(crate) struct ComponentSerializer {
+    pub(crate) name: String,
+    pub(crate) type_id: TypeId,
+    serialize: SerializeFn,
+}
+
+/// Borrowed set of a component type, serialized as an empty set if the type wasn't used yet.
+struct SetView<'a, T>(Option<View<'a, T>>);
+
+impl<T: Component + Serialize> Serialize for SetView<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match &self.0 {
+            Some(set) => set.serialize(serializer),
+            None => SparseSet::<Entity, T>::new().serialize(serializer),
+        }
+    }
+}
+
+fn serialize_set<T: Component + Serialize>(cm: &ComponentManager) -> Box<dyn erased_serde::Serialize + '_> {
+    Box::new(SetView(cm.get_components::<T>()))
+}
+
+impl ComponentManager {
+    ///
+    /// Includes the components of type `T` under `name` when the world is serialized,
+    /// see [`Manager::serialize_world`]. Registering a type again replaces its name.
+    ///
+    /// # Panics
+    ///
+    /// Panics if another component type is registered under the same name.
+    ///
+    pub fn register_serialize<T: Component + Serialize>(&mut self, name: &str) {
+        self.add_serializer(ComponentSerializer {
+            name: name.to_string(),
+            type_id: TypeId::of::<T>(),
+            serialize: serialize_set::<T>,
+        });
+    }
+}
+
+struct Components<'a>(&'a ComponentManager);
+
+impl Serialize for Components<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use ser::SerializeMap;
+
+        let serializers = self.0.serializers();
+        let mut map = serializer.serialize_map(Some(serializers.len()))?;
+        for component in serializers {
+            map.serialize_entry(&component.name, &(component.serialize)(self.0))?;
+        }
+        map.end()
+    }
+}
+
+///
+/// Serializable view of a whole world, see [`Manager::serialize_world`].
+/// The comp_manager stays borrowed while the view lives.
+///
+pub struct WorldSerializer<'a> {
+    entities: Vec<Entity>,
+    comp_manager: ComponentView<'a>,
+}
+
+impl Serialize for WorldSerializer<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("World", 2)?;
+        state.serialize_field("entities", &self.entities)?;
+        state.serialize_field("components", &Components(&self.comp_manager))?;
+        state.end()
+    }
+}
+
+impl Manager {
+    ///
+    /// Returns a serializable view of the alive entities and of every component type registered
+    /// with [`ComponentManager::register_serialize`], keyed by their names. Each set is written
+    /// straight to the serializer, nothing is buffered.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the comp_manager is borrowed mutably.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    /// #[derive(serde::Serialize)]
+    /// struct Hp(u32);
+    ///
+    /// register_components!(Hp);
+    ///
+    /// let manager = Manager::new();
+    /// manager.get_comp_manager_mut().register_serialize::<Hp>("hp");
+    /// let entity = manager.add_entity();
+    /// manager.add_component(&entity, Hp(10));
+    ///
+    /// let json = serde_json::to_string(&manager.serialize_world()).unwrap();
+    /// assert_eq!(json, r#"{"entities":[[0,0]],"components":{"hp":{"group_len":0,"entries":[[[0,0],10]]}}}"#);
+    /// ```
+    pub fn serialize_world(&self) -> WorldSerializer<'_> {
+        let comp_manager = self.get_comp_manager();
+        let entities = self.ent_handler.lock().unwrap().slots().iter().enumerate()
+            .filter(|(i, entity)| entity.get_index() == *i as u32)
+            .map(|(_, entity)| *entity)
+            .collect();
+        WorldSerializer { entities, comp_manager }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+    enum Shape {
+        Circle { radius: f32 },
+        Rect(u8, u8),
+        Point,
+    }
+
+    #[derive(serde::Serialize)]
+    struct Pos(i32, i32);
+    #[derive(serde::Serialize)]
+    struct Vel(i32, i32);
+
+    group!(Pos, Vel);
+    register_components!(Shape);
+
+    #[test]
+    fn sparse_set_round_trip() {
+        let mut set = SparseSet::<Entity, Shape>::new();
+        set.add(&Entity::new(4, 1), Shape::Point);
+        set.add(&Entity::new(2, 0), Shape::Rect(1, 2));
+        set.add(&Entity::new(7, 3), Shape::Circle { radius: 0.5 });
+        set.group(&Entity::new(7, 3));
+
+        let json = serde_json::to_string(&set).unwrap();
+        let loaded: SparseSet<Entity, Shape> = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.group_len(), 1);
+        for i in 0..3 {
+            assert_eq!(loaded.key_at(i), set.key_at(i));
+            assert_eq!(loaded.component_at(i), set.component_at(i));
+        }
+
+        assert!(serde_json::from_str::<SparseSet<Entity, Shape>>(r#"{"group_len":2,"entries":[[[0,0],"Point"]]}"#).is_err());
+        assert!(serde_json::from_str::<SparseSet<Entity, Shape>>(r#"{"group_len":0,"entries":[[[0,0],"Point"],[[0,0],"Point"]]}"#).is_err());
+        assert!(serde_json::from_str::<SparseSet<Entity, i32>>(r#"{"group_len":2,"entries":[[[0,0],1],[[0,1],2]]}"#).is_err());
+        if cfg!(not(feature = "entity64")) {
+            assert!(serde_json::from_str::<Entity>("[0,16384]").is_err());
+        }
+    }
+
+    #[test]
+    fn world() {
+        let manager = Manager::new();
+        {
+            let mut cm = manager.get_comp_manager_mut();
+            cm.register_serialize::<Pos>("pos");
+            cm.register_serialize::<Vel>("vel");
+            cm.register_serialize::<Shape>("shape");
+        }
+        let e: Vec<Entity> = (0..3).map(|_| manager.add_entity()).collect();
+        manager.add_component(&e[0], Pos(1, 2));
+        manager.add_component(&e[2], Pos(3, 4));
+        manager.add_component(&e[2], Vel(0, 1));
+        manager.add_component(&e[2], Shape::Circle { radius: 1.5 });
+        manager.kill_entity(e[1]);
+
+        let json = serde_json::to_value(manager.serialize_world()).unwrap();
+        assert_eq!(json, serde_json::json!({
+            "entities": [[0, 0], [2, 0]],
+            "components": {
+                "pos": { "group_len": 1, "entries": [[[2, 0], [3, 4]], [[0, 0], [1, 2]]] },
+                "vel": { "group_len": 1, "entries": [[[2, 0], [0, 1]]] },
+                "shape": { "group_len": 0, "entries": [[[2, 0], { "Circle": { "radius": 1.5 } }]] },
+            },
+        }));
+    }
+}
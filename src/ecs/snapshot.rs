@@ -0,0 +1,196 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::ecs::entity_handler::EntityHandler;
+use crate::sparse_set::SparseSet;
+use crate::{Component, ComponentManager, Entity, Manager};
+
+type RestoreFn = fn(&mut ComponentManager, &(dyn Any + Send + Sync), &[TypeId]);
+
+///
+/// Copy of the set of a single component type, together with the function putting it back.
+///
+#[derive(Clone)]
+pub(crate) struct ComponentSnapshot {
+    pub(crate) set: Arc<dyn Any + Send + Sync>,
+    pub(crate) restore: RestoreFn,
+}
+
+pub(crate) fn snapshot_set<T: Component + Clone>(cm: &ComponentManager) -> ComponentSnapshot {
+    let set: SparseSet<Entity, T> = cm.get_components::<T>().unwrap().clone();
+    ComponentSnapshot {
+        set: Arc::new(set),
+        restore: restore_set::<T>,
+    }
+}
+
+fn restore_set<T: Component + Clone>(cm: &mut ComponentManager, set: &(dyn Any + Send + Sync), cleared: &[TypeId]) {
+    let set = set.downcast_ref::<SparseSet<Entity, T>>().unwrap();
+    cm.replace_components(set.clone(), cleared);
+}
+
+///
+/// Copy of a world taken by [`Manager::snapshot`]. Holds the entity allocator and the sets of every
+/// component type registered with [`ComponentManager::register_cloneable`]. Cloning a snapshot
+/// is cheap, the sets are shared between the clones.
+///
+#[derive(Clone)]
+pub struct Snapshot {
+    entities: EntityHandler,
+    components: HashMap<TypeId, ComponentSnapshot>,
+}
+
+impl Snapshot {
+    /// Returns true if the snapshot holds the components of type `T`.
+    pub fn contains<T: Component>(&self) -> bool {
+        self.components.contains_key(&TypeId::of::<T>())
+    }
+}
+
+impl Manager {
+    ///
+    /// Takes a snapshot of the entities and of every component type registered as cloneable.
+    /// Resources, events, relations and the schedule are not part of it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any cloneable component is borrowed mutably.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    /// #[derive(Clone)]
+    /// struct Hp(u32);
+    ///
+    /// register_components!(Hp);
+    ///
+    /// let manager = Manager::new();
+    /// manager.get_comp_manager_mut().register_cloneable::<Hp>();
+    ///
+    /// let entity = manager.add_entity();
+    /// manager.add_component(&entity, Hp(10));
+    /// let snapshot = manager.snapshot();
+    ///
+    /// manager.kill_entity(entity);
+    /// manager.add_entity();
+    ///
+    /// manager.restore(&snapshot);
+    /// assert!(manager.entity_alive(entity));
+    /// assert_eq!(10, manager.get_comp_manager().get_components::<Hp>().unwrap().get(&entity).unwrap().0);
+    /// ```
+    pub fn snapshot(&self) -> Snapshot {
+        let comp_manager = self.get_comp_manager();
+        Snapshot {
+            entities: self.ent_handler.lock().unwrap().clone(),
+            components: comp_manager.snapshot_components(),
+        }
+    }
+
+    ///
+    /// Puts the world back into the state of the snapshot, with identical entity ids, versions and
+    /// packed order of the component sets. Components of types that are not in the snapshot are
+    /// cleared, entities grouped with them are ungrouped. All relations are cleared. No hooks are fired.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the comp_manager is borrowed.
+    pub fn restore(&self, snapshot: &Snapshot) {
+        let mut comp_manager = self.get_comp_manager_mut();
+        *self.ent_handler.lock().unwrap() = snapshot.entities.clone();
+        let kept: Vec<TypeId> = snapshot.components.keys().copied().collect();
+        let cleared = comp_manager.clear_components(&kept);
+        for component in snapshot.components.values() {
+            (component.restore)(&mut comp_manager, &*component.set, &cleared);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone)]
+    struct Pos(i32);
+    #[derive(Clone)]
+    struct Vel(i32);
+    struct Sprite;
+    #[derive(Clone)]
+    struct Hp(u32);
+    struct Shield;
+
+    group!(Pos, Vel);
+    group_partial!(Hp => Shield);
+    register_components!(Sprite, Shield);
+
+    fn manager() -> Manager {
+        let manager = Manager::new();
+        let mut cm = manager.get_comp_manager_mut();
+        cm.register_cloneable::<Pos>();
+        cm.register_cloneable::<Vel>();
+        cm.register_cloneable::<Hp>();
+        drop(cm);
+        manager
+    }
+
+    fn keys<T: Component>(manager: &Manager) -> Vec<Entity> {
+        let cm = manager.get_comp_manager();
+        let set = cm.get_components::<T>().unwrap();
+        (0..set.len()).map(|i| *set.key_at(i).unwrap()).collect()
+    }
+
+    #[test]
+    fn restore_identical() {
+        let manager = manager();
+        let e: Vec<Entity> = (0..6).map(|_| manager.add_entity()).collect();
+        for (i, entity) in e.iter().enumerate() {
+            manager.add_component(entity, Pos(i as i32));
+            if i % 2 == 0 {
+                manager.add_component(entity, Vel(i as i32));
+            }
+        }
+        manager.kill_entity(e[2]);
+        manager.kill_entity(e[5]);
+        let snapshot = manager.snapshot();
+        let pos = keys::<Pos>(&manager);
+        let vel = keys::<Vel>(&manager);
+
+        manager.kill_entity(e[0]);
+        manager.add_component(&e[1], Vel(1));
+        let spawned = manager.add_entity();
+        manager.add_component(&spawned, Pos(9));
+
+        manager.restore(&snapshot.clone());
+        assert_eq!(keys::<Pos>(&manager), pos);
+        assert_eq!(keys::<Vel>(&manager), vel);
+        let cm = manager.get_comp_manager();
+        assert_eq!(cm.group_len::<Pos>(), 2);
+        assert_eq!(cm.get_components::<Pos>().unwrap().get(&e[1]).unwrap().0, 1);
+        assert_eq!(cm.get_components::<Vel>().unwrap().get(&e[4]).unwrap().0, 4);
+        drop(cm);
+        assert!(manager.entity_alive(e[0]) && !manager.entity_alive(spawned));
+        // The free list is restored too, so the next entities reuse the same slots.
+        assert_eq!(manager.add_entity(), Entity::new(5, 1));
+        assert_eq!(manager.add_entity(), Entity::new(2, 1));
+    }
+
+    #[test]
+    fn restore_clears_others() {
+        let manager = manager();
+        let first = manager.add_entity();
+        manager.add_component(&first, Sprite);
+        manager.add_component(&first, Shield);
+        manager.add_component(&first, Hp(3));
+        assert_eq!(manager.get_comp_manager().group_len::<Hp>(), 1);
+
+        let snapshot = manager.snapshot();
+        assert!(snapshot.contains::<Hp>() && !snapshot.contains::<Sprite>());
+        manager.restore(&snapshot);
+
+        let cm = manager.get_comp_manager();
+        assert_eq!(cm.get_components::<Hp>().unwrap().get(&first).unwrap().0, 3);
+        assert!(!cm.has_component::<Sprite>(&first) && !cm.has_component::<Shield>(&first));
+        assert_eq!(cm.group_len::<Hp>(), 0);
+    }
+}
@@ -1,20 +1,85 @@
 
-use std::collections::HashMap;
+/// Amount of slots in every page of the sparse array.
+const PAGE_SIZE: usize = 4096;
+/// Marks an unused slot in a page.
+const EMPTY: u32 = u32::MAX;
+
+type Page = Box<[u32; PAGE_SIZE]>;
+
+///
+/// Change ticks of a single entry. Ticks count up as systems run, and an entry is
+/// added or changed since a system last ran if its tick is newer than the system's last run.
+/// Comparisons wrap, so they stay correct as long as no tick is more than `u32::MAX / 2` runs old.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ComponentTicks {
+    pub added: u32,
+    pub changed: u32,
+}
+
+impl ComponentTicks {
+    fn new(tick: u32) -> Self {
+        ComponentTicks { added: tick, changed: tick }
+    }
+
+    /// Returns true if the entry was added after `last_run`, as seen at `this_run`.
+    pub fn is_added(&self, last_run: u32, this_run: u32) -> bool {
+        is_newer(self.added, last_run, this_run)
+    }
+
+    /// Returns true if the entry was added or changed after `last_run`, as seen at `this_run`.
+    pub fn is_changed(&self, last_run: u32, this_run: u32) -> bool {
+        is_newer(self.changed, last_run, this_run)
+    }
+}
+
+fn is_newer(tick: u32, last_run: u32, this_run: u32) -> bool {
+    this_run.wrapping_sub(tick) < this_run.wrapping_sub(last_run)
+}
+
+///
+/// Trait for keys usable in a sparse set. Keys are stored in the sparse array
+/// at their sparse index. Keys sharing an index, such as different versions of
+/// the same entity, are told apart by comparing them to the stored key.
+/// 
+pub trait SparseKey: std::cmp::Eq + std::fmt::Debug + std::marker::Copy {
+    fn sparse_index(&self) -> usize;
+}
+
+impl SparseKey for u32 {
+    fn sparse_index(&self) -> usize {
+        *self as usize
+    }
+}
+
+impl SparseKey for usize {
+    fn sparse_index(&self) -> usize {
+        *self
+    }
+}
 
 ///
 /// A sparse set data type. Made to have very efficient insert and iterations.
 /// This sparse set is specially made for this key component system so it
 /// contains speciall features such as grouping.
 /// 
+/// The sparse array is paged, so only pages containing keys are allocated.
+/// 
+/// Every entry has [`ComponentTicks`], stamped with the tick of the set when the entry is
+/// added or mutably accessed. The component manager sets the tick whenever it lends the set out mutably.
+/// 
+#[derive(Clone)]
 pub struct SparseSet<Key, Value> {
     comp_array: Vec<Value>,
     entity_array: Vec<Key>,
-    sparse_array: HashMap<Key, usize>,
+    ticks_array: Vec<ComponentTicks>,
+    sparse_array: Vec<Option<Page>>,
     next_group: usize,
+    change_tick: u32,
 }
 
 impl<Key, Value> SparseSet<Key, Value>
-where Key: std::cmp::Eq + std::hash::Hash + std::fmt::Debug + std::marker::Copy {
+where Key: SparseKey {
 
     ///
     /// Creates a new sparse set. 
@@ -30,14 +95,36 @@ where Key: std::cmp::Eq + std::hash::Hash + std::fmt::Debug + std::marker::Copy
         SparseSet {
             comp_array: Vec::new(),
             entity_array: Vec::new(),
-            sparse_array: HashMap::new(),
+            ticks_array: Vec::new(),
+            sparse_array: Vec::new(),
             next_group: 0,
+            change_tick: 0,
         }
     }
 
     ///
-    /// Adds a new entry to the sparse set. If entry allready exsists, nothing happens.
-    /// This might change later though.
+    /// Reserves capacity for at least `additional` more entries in the packed arrays.
+    /// 
+    /// # Examples
+    /// 
+    /// ```
+    /// use rust_ecs::sparse_set::SparseSet;
+    /// 
+    /// let mut set = SparseSet::<u32, i32>::new();
+    /// set.reserve(10);
+    /// ```
+    pub fn reserve(&mut self, additional: usize) {
+        self.comp_array.reserve(additional);
+        self.entity_array.reserve(additional);
+        self.ticks_array.reserve(additional);
+    }
+
+    ///
+    /// Adds a new entry to the sparse set. If the entry allready exsists, its value is
+    /// replaced in place, marked changed and the old value is returned. An entry of another key with the
+    /// same sparse index, like an older version of an entity, is replaced in place by the new key and its
+    /// value is returned, the entry keeps its position and counts as added.
+    /// [`ComponentManager`](crate::ComponentManager) removes such entries first, so their hooks fire.
     ///
     /// # Examples
     /// 
@@ -46,17 +133,28 @@ where Key: std::cmp::Eq + std::hash::Hash + std::fmt::Debug + std::marker::Copy
     /// 
     /// let mut set = SparseSet::<u32, i32>::new();
     /// 
-    /// set.add(&0, 2);
+    /// assert_eq!(None, set.add(&0, 2));
+    /// assert_eq!(Some(2), set.add(&0, 3));
+    /// assert_eq!(3, *set.get(&0).unwrap());
     /// ```
-    pub fn add(&mut self, key: &Key, value: Value) {
-        
-        //std::mem::replace(self.comp_array.get_mut(*index).unwrap(), value);
-        //TODO check for existing value at index
-        if !self.sparse_array.contains_key(key) {
-            self.sparse_array.insert(*key, self.comp_array.len());
-            self.comp_array.push(value);
-            self.entity_array.push(*key);
+    pub fn add(&mut self, key: &Key, value: Value) -> Option<Value> {
+        match self.slot(key) {
+            Some(index) if self.entity_array[index] == *key => {
+                self.ticks_array[index].changed = self.change_tick;
+                return Some(std::mem::replace(&mut self.comp_array[index], value));
+            },
+            Some(index) => {
+                self.entity_array[index] = *key;
+                self.ticks_array[index] = ComponentTicks::new(self.change_tick);
+                return Some(std::mem::replace(&mut self.comp_array[index], value));
+            },
+            None => {},
         }
+        self.set_slot(key, self.comp_array.len());
+        self.comp_array.push(value);
+        self.entity_array.push(*key);
+        self.ticks_array.push(ComponentTicks::new(self.change_tick));
+        None
     }
 
     ///
@@ -74,12 +172,33 @@ where Key: std::cmp::Eq + std::hash::Hash + std::fmt::Debug + std::marker::Copy
     /// assert_eq!(2, *set.get(&0).unwrap());
     /// ```
     pub fn get(&self, key: &Key) -> Option<&Value> {
-        match self.sparse_array.get(key) {
-            Some(i) => self.comp_array.get(*i),
+        match self.index_of(key) {
+            Some(i) => self.comp_array.get(i),
             None => None,
         }
     }
 
+    ///
+    /// Returns an option of type &mut Value containing the mutable Value belonging to the key.
+    /// Marks the entry changed.
+    ///
+    /// # Examples
+    /// 
+    /// ```
+    /// use rust_ecs::sparse_set::SparseSet;
+    /// 
+    /// let mut set = SparseSet::<u32, i32>::new();
+    /// 
+    /// set.add(&0, 2);
+    /// *set.get_mut(&0).unwrap() += 1;
+    /// 
+    /// assert_eq!(3, *set.get(&0).unwrap());
+    /// ```
+    pub fn get_mut(&mut self, key: &Key) -> Option<&mut Value> {
+        let index = self.index_of(key)?;
+        self.component_at_mut(index)
+    }
+
     ///
     /// Returns an option of type &Value containing the Value at the given position in the packed array.
     ///
@@ -100,6 +219,7 @@ where Key: std::cmp::Eq + std::hash::Hash + std::fmt::Debug + std::marker::Copy
 
     ///
     /// Returns an option of type &mut Value containing the mutable Value at the given position in the packed array.
+    /// Marks the entry changed.
     ///
     /// # Examples
     /// 
@@ -113,9 +233,48 @@ where Key: std::cmp::Eq + std::hash::Hash + std::fmt::Debug + std::marker::Copy
     /// assert_eq!(2, *set.component_at_mut(0).unwrap());
     /// ```
     pub fn component_at_mut(&mut self, index: usize) -> Option<&mut Value> {
+        let ticks = self.ticks_array.get_mut(index)?;
+        ticks.changed = self.change_tick;
         self.comp_array.get_mut(index)
     }
 
+    ///
+    /// Returns the change ticks of the entry at the given position in the packed array.
+    ///
+    /// # Examples
+    /// 
+    /// ```
+    /// use rust_ecs::sparse_set::SparseSet;
+    /// 
+    /// let mut set = SparseSet::<u32, i32>::new();
+    /// 
+    /// set.set_change_tick(3);
+    /// set.add(&21, 2);
+    /// set.set_change_tick(5);
+    /// set.component_at_mut(0);
+    /// 
+    /// assert_eq!(3, set.ticks_at(0).unwrap().added);
+    /// assert_eq!(5, set.ticks_at(0).unwrap().changed);
+    /// ```
+    pub fn ticks_at(&self, index: usize) -> Option<&ComponentTicks> {
+        self.ticks_array.get(index)
+    }
+
+    /// Returns the change ticks of the entry belonging to the key.
+    pub fn ticks(&self, key: &Key) -> Option<&ComponentTicks> {
+        self.ticks_at(self.index_of(key)?)
+    }
+
+    /// The tick added and changed entries are stamped with.
+    pub fn change_tick(&self) -> u32 {
+        self.change_tick
+    }
+
+    /// Sets the tick added and changed entries are stamped with.
+    pub fn set_change_tick(&mut self, tick: u32) {
+        self.change_tick = tick;
+    }
+
     ///
     /// Returns an option of type &Key containing the key which owns the i:th component in the packed array.
     ///
@@ -150,10 +309,7 @@ where Key: std::cmp::Eq + std::hash::Hash + std::fmt::Debug + std::marker::Copy
     /// assert!(!set.contains(&3));
     /// ```
     pub fn contains(&self, key: &Key) -> bool {
-        match self.sparse_array.get(key) {
-            Some(_) => true,
-            None => false,
-        }
+        self.index_of(key).is_some()
     }
 
     ///
@@ -176,12 +332,110 @@ where Key: std::cmp::Eq + std::hash::Hash + std::fmt::Debug + std::marker::Copy
         self.entity_array.len()
     }
 
+    ///
+    /// Returns true if the sparse set contains no components/entities.
+    ///
+    /// # Examples
+    /// 
+    /// ```
+    /// use rust_ecs::sparse_set::SparseSet;
+    /// 
+    /// let set = SparseSet::<u32, i32>::new();
+    /// 
+    /// assert!(set.is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.entity_array.is_empty()
+    }
+
+    ///
+    /// Returns the amount of grouped entries. These are packed at the front of the set.
+    ///
+    /// # Examples
+    /// 
+    /// ```
+    /// use rust_ecs::sparse_set::SparseSet;
+    /// 
+    /// let mut set = SparseSet::<u32, i32>::new();
+    /// 
+    /// set.add(&0, 2);
+    /// set.add(&1, 2);
+    /// set.group(&1);
+    /// 
+    /// assert_eq!(1, set.group_len());
+    /// assert_eq!(1, *set.key_at(0).unwrap());
+    /// ```
+    pub fn group_len(&self) -> usize {
+        self.next_group
+    }
+
+    /// Returns the position of the key in the packed array.
+    pub(crate) fn index_of(&self, key: &Key) -> Option<usize> {
+        match self.slot(key) {
+            Some(index) if self.entity_array[index] == *key => Some(index),
+            _ => None,
+        }
+    }
+
+    /// Returns the other key stored at the key's sparse index, like an older version of an entity.
+    pub(crate) fn stale_key(&self, key: &Key) -> Option<Key> {
+        self.slot(key).map(|index| self.entity_array[index]).filter(|other| other != key)
+    }
+
+    /// Returns the position stored at the key's sparse index, which might belong to another version of the key.
+    fn slot(&self, key: &Key) -> Option<usize> {
+        let sparse_index = key.sparse_index();
+        let page = self.sparse_array.get(sparse_index / PAGE_SIZE)?.as_ref()?;
+        match page[sparse_index % PAGE_SIZE] {
+            EMPTY => None,
+            index => Some(index as usize),
+        }
+    }
+
+    fn set_slot(&mut self, key: &Key, index: usize) {
+        let sparse_index = key.sparse_index();
+        let page_index = sparse_index / PAGE_SIZE;
+        if page_index >= self.sparse_array.len() {
+            self.sparse_array.resize_with(page_index + 1, || None);
+        }
+        let page = self.sparse_array[page_index].get_or_insert_with(|| Box::new([EMPTY; PAGE_SIZE]));
+        page[sparse_index % PAGE_SIZE] = index as u32;
+    }
+
+    fn clear_slot(&mut self, key: &Key) {
+        let sparse_index = key.sparse_index();
+        if let Some(Some(page)) = self.sparse_array.get_mut(sparse_index / PAGE_SIZE) {
+            page[sparse_index % PAGE_SIZE] = EMPTY;
+        }
+    }
+
+    /// Swaps two entries in the packed arrays and updates the sparse array.
+    fn swap(&mut self, a: usize, b: usize) {
+        let key_a = self.entity_array[a];
+        let key_b = self.entity_array[b];
+        self.set_slot(&key_a, b);
+        self.set_slot(&key_b, a);
+        self.comp_array.swap(a, b);
+        self.entity_array.swap(a, b);
+        self.ticks_array.swap(a, b);
+    }
+
+    /// Returns a raw pointer to the start of the packed component array.
+    pub(crate) fn components_ptr_mut(&mut self) -> *mut Value {
+        self.comp_array.as_mut_ptr()
+    }
+
+    /// Returns a raw pointer to the start of the packed ticks array.
+    pub(crate) fn ticks_ptr_mut(&mut self) -> *mut ComponentTicks {
+        self.ticks_array.as_mut_ptr()
+    }
+
     pub fn print(&self) {
         print!("Keys ");
         for i in 0..self.len() {
             print!("{:?} ({}), ", self.entity_array.get(i).unwrap(), i);
         }
-        print!("\n");
+        println!();
         //TODO print sparse_array
     }
 
@@ -190,15 +444,9 @@ where Key: std::cmp::Eq + std::hash::Hash + std::fmt::Debug + std::marker::Copy
     /// Should not be used outside of the rust_ecs crate. Needs to be public because
     /// of grouping macro.
     pub fn group(&mut self, key: &Key) {
-        if self.sparse_array.contains_key(key) {
-            //Should never panic
-            let entity_array_index = *self.sparse_array.get(key).unwrap();
+        if let Some(entity_array_index) = self.index_of(key) {
             if self.next_group < entity_array_index {
-                let ungrouped = *self.entity_array.get(self.next_group).unwrap();
-                let temp = self.sparse_array.insert(*key, self.next_group).unwrap();
-                self.sparse_array.insert(ungrouped, temp);
-                self.comp_array.swap(self.next_group, entity_array_index);
-                self.entity_array.swap(self.next_group, entity_array_index);
+                self.swap(self.next_group, entity_array_index);
                 self.next_group += 1;
             } else if self.next_group == entity_array_index {
                 self.next_group += 1;
@@ -213,18 +461,13 @@ where Key: std::cmp::Eq + std::hash::Hash + std::fmt::Debug + std::marker::Copy
     /// Should not be used outside of the rust_ecs crate. Needs to be public because
     /// of grouping macro.
     pub fn ungroup(&mut self, key: &Key) {
-        if self.sparse_array.contains_key(key) {
-            //Should never panic
-            let entity_array_index = *self.sparse_array.get(key).unwrap();
-            if entity_array_index < self.next_group - 1 {
-                let last_grouped = *self.entity_array.get(self.next_group-1).unwrap();
-                let temp = self.sparse_array.insert(*key, self.next_group-1).unwrap();
-                self.sparse_array.insert(last_grouped, temp);
-                self.comp_array.swap(self.next_group-1, entity_array_index);
-                self.entity_array.swap(self.next_group-1, entity_array_index);
+        if let Some(entity_array_index) = self.index_of(key) {
+            if entity_array_index < self.next_group {
+                let last_grouped_index = self.next_group - 1;
+                if entity_array_index < last_grouped_index {
+                    self.swap(last_grouped_index, entity_array_index);
+                }
                 self.next_group -= 1;
-            } else if entity_array_index == self.next_group - 1 {
-                self.next_group -= 1;   
             }
         } else {
             print!("No key with id {:?}", key);
@@ -232,8 +475,8 @@ where Key: std::cmp::Eq + std::hash::Hash + std::fmt::Debug + std::marker::Copy
     }
 
     ///
-    /// Removes an key and its component from the set. Ungroups if they are grouped.
-    /// Removes are done with swap to stay somewhat efficient.
+    /// Removes an key and its component from the set and returns the component.
+    /// Ungroups if they are grouped. Removes are done with swap to stay somewhat efficient.
     ///
     /// # Examples
     /// 
@@ -243,27 +486,34 @@ where Key: std::cmp::Eq + std::hash::Hash + std::fmt::Debug + std::marker::Copy
     /// let mut set = SparseSet::<u32, i32>::new();
     /// 
     /// set.add(&0, 2);
-    /// set.remove(&0);
     /// 
+    /// assert_eq!(Some(2), set.remove(&0));
     /// assert!(!set.contains(&0));
     /// ```
-    pub fn remove(&mut self, key: &Key) {
-        if self.sparse_array.contains_key(key) {
-            let index = *self.sparse_array.get(&key).unwrap();
-            if index < self.next_group {
-                //TODO ungroup by index for efficency
-                self.ungroup(&key);
-            }
-            self.sparse_array.remove(key);
-            self.comp_array.swap_remove(index);
-            self.entity_array.swap_remove(index);
-
-            //Updates sparse array if a swap occured
-            if index < self.len() {
-                let swaped_key = self.entity_array.get(index).unwrap();
-                self.sparse_array.insert(*swaped_key, index);
-            }
+    pub fn remove(&mut self, key: &Key) -> Option<Value> {
+        self.index_of(key)?;
+        //TODO ungroup by index for efficency
+        self.ungroup(key);
+        //Ungrouping might have moved the key
+        let index = self.index_of(key).unwrap();
+        self.clear_slot(key);
+        let value = self.comp_array.swap_remove(index);
+        self.entity_array.swap_remove(index);
+        self.ticks_array.swap_remove(index);
+
+        //Updates sparse array if a swap occured
+        if index < self.len() {
+            let swaped_key = self.entity_array[index];
+            self.set_slot(&swaped_key, index);
         }
+        Some(value)
+    }
+}
+
+impl<Key, Value> Default for SparseSet<Key, Value>
+where Key: SparseKey {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -307,6 +557,49 @@ mod tests {
         set.add(&Entity::new(0, 0), TestType{data: 0});
         assert_eq!(set.len(), 1);
     }
+
+    #[test]
+    fn ticks() {
+        let mut set = SparseSet::<Entity, TestType>::new();
+        set.set_change_tick(1);
+        set.add(&Entity::new(0, 0), TestType{data: 0});
+        set.add(&Entity::new(1, 0), TestType{data: 1});
+        set.set_change_tick(2);
+        set.add(&Entity::new(2, 0), TestType{data: 2});
+        set.add(&Entity::new(0, 0), TestType{data: 3});
+        set.remove(&Entity::new(1, 0));
+        set.group(&Entity::new(2, 0));
+
+        let e0 = set.ticks(&Entity::new(0, 0)).unwrap();
+        assert_eq!((e0.added, e0.changed), (1, 2));
+        assert!(!e0.is_added(1, 3));
+        assert!(e0.is_changed(1, 3));
+        let e2 = set.ticks(&Entity::new(2, 0)).unwrap();
+        assert_eq!((e2.added, e2.changed), (2, 2));
+        assert!(!e2.is_added(2, 3));
+    }
+
+    #[test]
+    fn ticks_wrap() {
+        let ticks = ComponentTicks::new(u32::MAX);
+        assert!(ticks.is_added(u32::MAX - 1, 2));
+        assert!(!ticks.is_added(u32::MAX, 2));
+        assert!(ComponentTicks::new(1).is_changed(u32::MAX, 2));
+        assert!(!ComponentTicks::new(u32::MAX - 1).is_changed(u32::MAX, 2));
+    }
+
+    #[test]
+    fn sparse_set_replace_grouped() {
+        let mut set = SparseSet::<Entity, TestType>::new();
+        set.add(&Entity::new(0, 0), TestType{data: 0});
+        set.add(&Entity::new(1, 0), TestType{data: 1});
+        set.group(&Entity::new(1, 0));
+
+        assert_eq!(set.add(&Entity::new(1, 0), TestType{data: 2}).unwrap().data, 1);
+        assert_eq!(set.key_at(0), Some(&Entity::new(1, 0)));
+        assert_eq!(set.get(&Entity::new(1, 0)).unwrap().data, 2);
+        assert_eq!(set.group_len(), 1);
+    }
     
     #[test]
     fn sparse_set_get_by_keyid() {
@@ -568,4 +861,105 @@ mod tests {
         
         assert_eq!(set.get(&Entity::new(0, 0)).unwrap().data, 7);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn sparse_set_remove_returns_value() {
+        let mut set = SparseSet::<Entity, TestType>::new();
+        set.add(&Entity::new(0, 0), TestType{data: 7});
+        set.add(&Entity::new(2, 0), TestType{data: 8});
+    
+        assert_eq!(set.remove(&Entity::new(0, 0)).unwrap().data, 7);
+        assert!(set.remove(&Entity::new(0, 0)).is_none());
+        assert_eq!(set.get(&Entity::new(2, 0)).unwrap().data, 8);
+    }
+    
+    #[test]
+    fn sparse_set_remove_grouped() {
+        let mut set = SparseSet::<Entity, TestType>::new();
+        set.add(&Entity::new(0, 0), TestType{data: 7});
+        set.add(&Entity::new(15, 0), TestType{data: 3});
+        set.add(&Entity::new(3, 0), TestType{data: 4});
+        set.add(&Entity::new(9, 0), TestType{data: 25});
+        set.group(&Entity::new(0, 0));
+        set.group(&Entity::new(15, 0));
+        set.group(&Entity::new(3, 0));
+    
+        assert_eq!(set.remove(&Entity::new(0, 0)).unwrap().data, 7);
+    
+        assert_eq!(set.len(), 3);
+        assert_eq!(set.next_group, 2);
+    
+        assert_eq!(*set.key_at(0).unwrap(), Entity::new(3, 0));
+        assert_eq!(*set.key_at(1).unwrap(), Entity::new(15, 0));
+        assert_eq!(*set.key_at(2).unwrap(), Entity::new(9, 0));
+    
+        assert_eq!(set.get(&Entity::new(3, 0)).unwrap().data, 4);
+        assert_eq!(set.get(&Entity::new(15, 0)).unwrap().data, 3);
+        assert_eq!(set.get(&Entity::new(9, 0)).unwrap().data, 25);
+    }
+    
+    #[test]
+    fn sparse_set_ungroup_without_group() {
+        let mut set = SparseSet::<Entity, TestType>::new();
+        set.add(&Entity::new(0, 0), TestType{data: 7});
+        set.ungroup(&Entity::new(0, 0));
+    
+        assert_eq!(set.next_group, 0);
+        assert_eq!(set.get(&Entity::new(0, 0)).unwrap().data, 7);
+    }
+
+    #[test]
+    fn sparse_set_version_mismatch() {
+        let mut set = SparseSet::<Entity, TestType>::new();
+        set.add(&Entity::new(3, 1), TestType{data: 7});
+    
+        assert!(set.get(&Entity::new(3, 0)).is_none());
+        assert!(!set.contains(&Entity::new(3, 2)));
+        assert!(set.remove(&Entity::new(3, 0)).is_none());
+        assert_eq!(set.get(&Entity::new(3, 1)).unwrap().data, 7);
+    }
+    
+    #[test]
+    fn sparse_set_stale_version() {
+        let mut set = SparseSet::<Entity, TestType>::new();
+        set.add(&Entity::new(3, 0), TestType{data: 7});
+        set.add(&Entity::new(4, 0), TestType{data: 8});
+    
+        assert_eq!(set.stale_key(&Entity::new(3, 1)), Some(Entity::new(3, 0)));
+        assert_eq!(set.stale_key(&Entity::new(3, 0)), None);
+        assert_eq!(set.stale_key(&Entity::new(5, 0)), None);
+    }
+    
+    #[test]
+    fn sparse_set_add_stale_version() {
+        let mut set = SparseSet::<Entity, TestType>::new();
+        set.set_change_tick(1);
+        set.add(&Entity::new(3, 0), TestType{data: 7});
+        set.add(&Entity::new(4, 0), TestType{data: 8});
+        set.set_change_tick(2);
+    
+        assert_eq!(set.add(&Entity::new(3, 1), TestType{data: 9}).unwrap().data, 7);
+        assert_eq!(set.len(), 2);
+        assert!(!set.contains(&Entity::new(3, 0)));
+        assert_eq!(*set.key_at(0).unwrap(), Entity::new(3, 1));
+        assert_eq!(set.get(&Entity::new(3, 1)).unwrap().data, 9);
+        assert_eq!(set.ticks(&Entity::new(3, 1)).unwrap().added, 2);
+    }
+    
+    #[test]
+    fn sparse_set_pages() {
+        let mut set = SparseSet::<u32, u32>::new();
+        set.add(&(PAGE_SIZE as u32 * 3 + 5), 1);
+        set.add(&2, 2);
+    
+        assert_eq!(set.sparse_array.len(), 4);
+        assert!(set.sparse_array[1].is_none());
+        assert_eq!(*set.get(&(PAGE_SIZE as u32 * 3 + 5)).unwrap(), 1);
+        assert!(!set.contains(&(PAGE_SIZE as u32 + 5)));
+        assert!(!set.contains(&(PAGE_SIZE as u32 * 10)));
+    
+        set.remove(&2);
+        assert_eq!(*set.get(&(PAGE_SIZE as u32 * 3 + 5)).unwrap(), 1);
+        assert!(!set.contains(&2));
+    }
+}
@@ -1,21 +1,152 @@
+use std::any::TypeId;
 
 use crate::ComponentView;
+use crate::Commands;
+use crate::Component;
+use crate::query::Ticks;
+
+mod scheduler;
+mod schedule;
+mod param;
+
+pub(crate) use scheduler::run_parallel;
+pub(crate) use schedule::Schedule;
+pub use schedule::{Stage, ScheduleError, SystemConfig};
+pub use param::{SystemContext, SystemParam, SystemParamItem, SystemFunction};
 
 //Not stable yet
-//pub trait func_type = 'static + Fn(ComponentView);
+//pub trait func_type = 'static + Fn(ComponentView, &mut Commands);
+
+type SystemFn = Box<dyn Fn(ComponentView, &mut Commands) + Send + Sync>;
+
+///
+/// The component types a system reads and writes. Systems whose accesses don't conflict
+/// can run at the same time, see [`Manager::run_parallel`](crate::Manager::run_parallel).
+///
+/// # Examples
+///
+/// ```
+/// use rust_ecs::*;
+/// struct Pos(i32);
+/// struct Vel(i32);
+///
+/// register_components!(Pos, Vel);
+///
+/// let movement = Access::new().read::<Vel>().write::<Pos>();
+/// let render = Access::new().read::<Pos>();
+///
+/// assert!(movement.conflicts(&render));
+/// assert!(!render.conflicts(&Access::new().read::<Pos>()));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Access {
+    reads: Vec<TypeId>,
+    writes: Vec<TypeId>,
+    resource_reads: Vec<TypeId>,
+    resource_writes: Vec<TypeId>,
+    exclusive: bool,
+}
+
+impl Access {
+    /// Creates an access that touches no components.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// Creates an access that conflicts with every other access.
+    /// Used for systems that don't declare what they touch.
+    ///
+    pub fn exclusive() -> Self {
+        Access {
+            exclusive: true,
+            ..Self::default()
+        }
+    }
+
+    /// Declares that the component `T` is read.
+    pub fn read<T: Component>(mut self) -> Self {
+        self.reads.push(TypeId::of::<T>());
+        self
+    }
+
+    /// Declares that the component `T` is written.
+    pub fn write<T: Component>(mut self) -> Self {
+        self.writes.push(TypeId::of::<T>());
+        self
+    }
+
+    /// Declares that the resource `T` is read.
+    pub fn read_resource<T: Send + Sync + 'static>(mut self) -> Self {
+        self.resource_reads.push(TypeId::of::<T>());
+        self
+    }
+
+    /// Declares that the resource `T` is written.
+    pub fn write_resource<T: Send + Sync + 'static>(mut self) -> Self {
+        self.resource_writes.push(TypeId::of::<T>());
+        self
+    }
+
+    ///
+    /// Returns true if the two accesses can't run at the same time,
+    /// that is if either of them writes a component or resource the other one reads or writes.
+    ///
+    pub fn conflicts(&self, other: &Access) -> bool {
+        if self.exclusive || other.exclusive {
+            return true;
+        }
+        fn overlaps(reads: &[TypeId], writes: &[TypeId], other_reads: &[TypeId], other_writes: &[TypeId]) -> bool {
+            writes.iter().any(|t| other_reads.contains(t) || other_writes.contains(t))
+                || other_writes.iter().any(|t| reads.contains(t))
+        }
+        overlaps(&self.reads, &self.writes, &other.reads, &other.writes)
+            || overlaps(&self.resource_reads, &self.resource_writes, &other.resource_reads, &other.resource_writes)
+    }
+}
 
 pub struct System {
-    func_ptr: Box<dyn Fn(ComponentView)>  
+    func_ptr: SystemFn,
+    access: Access,
 }
 
 impl System {
-    pub fn new<F: 'static + Fn(ComponentView)>(func: F) -> Self {
+    pub fn new<F: 'static + Fn(ComponentView, &mut Commands) + Send + Sync>(func: F) -> Self {
+        Self::with_access(Access::exclusive(), func)
+    }
+
+    pub fn with_access<F: 'static + Fn(ComponentView, &mut Commands) + Send + Sync>(access: Access, func: F) -> Self {
         Self {
             func_ptr: Box::new(func),
+            access,
         }
     }
-    pub fn run(&self, cm_ref: ComponentView) {
-        (self.func_ptr)(cm_ref);
+
+    ///
+    /// Creates a system from a function taking [`SystemParam`]s. The access is derived from the parameters.
+    /// Every run advances the change tick and remembers it, so the queries of the next run
+    /// only see components added or changed since then as [`Added`](crate::Added) or [`Changed`](crate::Changed).
+    ///
+    pub fn from_function<Params, F: SystemFunction<Params>>(func: F) -> Self {
+        // The parameter states and the tick of the last run.
+        let state = std::sync::Mutex::new((F::State::default(), 0));
+        Self::with_access(F::access(), move |cm, commands| {
+            let mut state = state.lock().unwrap();
+            let (state, last_run) = &mut *state;
+            let ticks = Ticks { last_run: *last_run, this_run: cm.increment_change_tick() };
+            let context = SystemContext::new(commands.manager(), &cm, ticks);
+            func.run(&context, state);
+            *last_run = ticks.this_run;
+            commands.append(&mut context.into_commands());
+        })
+    }
+
+    pub fn access(&self) -> &Access {
+        &self.access
+    }
+
+    pub fn run(&self, cm_ref: ComponentView, commands: &mut Commands) {
+        (self.func_ptr)(cm_ref, commands);
     }
 }
 
@@ -24,4 +155,3 @@ impl System {
 
 //     fn priority() -> i8;
 // }
-
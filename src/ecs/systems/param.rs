@@ -0,0 +1,274 @@
+use std::sync::Mutex;
+
+use super::Access;
+use crate::ecs::commands::Command;
+use crate::query::{QueryData, QueryFilter, Ticks};
+use crate::{Commands, ComponentManager, Manager, Query, Res, ResMut};
+
+///
+/// Everything a typed system can borrow its parameters from while it runs.
+///
+pub struct SystemContext<'w> {
+    manager: &'w Manager,
+    cm: &'w ComponentManager,
+    ticks: Ticks,
+    commands: Mutex<Vec<Command>>,
+}
+
+impl<'w> SystemContext<'w> {
+    pub(crate) fn new(manager: &'w Manager, cm: &'w ComponentManager, ticks: Ticks) -> Self {
+        SystemContext {
+            manager,
+            cm,
+            ticks,
+            commands: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn manager(&self) -> &'w Manager {
+        self.manager
+    }
+
+    pub fn components(&self) -> &'w ComponentManager {
+        self.cm
+    }
+
+    /// The tick of the previous run of the system and the tick of the current one.
+    pub fn ticks(&self) -> Ticks {
+        self.ticks
+    }
+
+    /// Takes the commands recorded by the [`Commands`] parameters of the system.
+    pub(crate) fn into_commands(self) -> Vec<Command> {
+        self.commands.into_inner().unwrap()
+    }
+}
+
+///
+/// A parameter of a typed system, see [`Manager::add_system`].
+/// The manager resolves and borrows every parameter right before the system runs.
+///
+pub trait SystemParam {
+    /// Kept by the system between runs, like the read position of an [`EventReader`](crate::EventReader).
+    type State: Default + Send + 'static;
+    /// The parameter handed to the system, borrowing from the context and the state.
+    type Item<'w>;
+
+    /// Declares the components the parameter reads and writes.
+    fn access(access: Access) -> Access;
+
+    /// Prepares the manager for the parameter when a system using it is added.
+    fn init(_manager: &Manager) {}
+
+    ///
+    /// # Panics
+    ///
+    /// Panics if the parameter can't be borrowed.
+    fn fetch<'w>(context: &'w SystemContext<'_>, state: &'w mut Self::State) -> Self::Item<'w>;
+}
+
+pub type SystemParamItem<'w, P> = <P as SystemParam>::Item<'w>;
+
+impl<Q: QueryData, F: QueryFilter> SystemParam for Query<'_, Q, F> {
+    type State = ();
+    type Item<'w> = Query<'w, Q, F>;
+
+    fn access(access: Access) -> Access {
+        F::access(Q::access(access))
+    }
+
+    fn fetch<'w>(context: &'w SystemContext<'_>, _: &'w mut Self::State) -> Self::Item<'w> {
+        Query::with_ticks(context.components(), context.ticks()).unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+impl SystemParam for Commands<'_> {
+    type State = ();
+    type Item<'w> = Commands<'w>;
+
+    fn access(access: Access) -> Access {
+        access
+    }
+
+    fn fetch<'w>(context: &'w SystemContext<'_>, _: &'w mut Self::State) -> Self::Item<'w> {
+        Commands::with_sink(context.manager(), &context.commands)
+    }
+}
+
+impl<T: Send + Sync + 'static> SystemParam for Res<'_, T> {
+    type State = ();
+    type Item<'w> = Res<'w, T>;
+
+    fn access(access: Access) -> Access {
+        access.read_resource::<T>()
+    }
+
+    fn fetch<'w>(context: &'w SystemContext<'_>, _: &'w mut Self::State) -> Self::Item<'w> {
+        context.manager().resource::<T>()
+            .unwrap_or_else(|| panic!("Resource {} not found", std::any::type_name::<T>()))
+    }
+}
+
+impl<T: Send + Sync + 'static> SystemParam for ResMut<'_, T> {
+    type State = ();
+    type Item<'w> = ResMut<'w, T>;
+
+    fn access(access: Access) -> Access {
+        access.write_resource::<T>()
+    }
+
+    fn fetch<'w>(context: &'w SystemContext<'_>, _: &'w mut Self::State) -> Self::Item<'w> {
+        context.manager().resource_mut::<T>()
+            .unwrap_or_else(|| panic!("Resource {} not found", std::any::type_name::<T>()))
+    }
+}
+
+///
+/// A function whose arguments are all [`SystemParam`]s. Implemented for functions
+/// and closures of up to eight parameters.
+///
+pub trait SystemFunction<Params>: Send + Sync + 'static {
+    /// The states of every parameter.
+    type State: Default + Send + 'static;
+
+    /// Declares the components read and written by every parameter.
+    fn access() -> Access;
+
+    /// Prepares the manager for every parameter, see [`SystemParam::init`].
+    fn init(manager: &Manager);
+
+    fn run(&self, context: &SystemContext<'_>, state: &mut Self::State);
+}
+
+macro_rules! impl_system_function {
+    ($(($param:ident, $index:tt)),*) => {
+        // The parameter types double as variable names, and the context is unused without parameters.
+        #[allow(non_snake_case, unused_variables, clippy::too_many_arguments)]
+        impl<Func, $($param: SystemParam),*> SystemFunction<($($param,)*)> for Func
+        where
+            Func: Send + Sync + 'static,
+            for<'a> &'a Func: Fn($($param),*) + Fn($(SystemParamItem<'_, $param>),*),
+        {
+            type State = ($($param::State,)*);
+
+            fn access() -> Access {
+                let access = Access::new();
+                $(let access = $param::access(access);)*
+                access
+            }
+
+            fn init(manager: &Manager) {
+                $($param::init(manager);)*
+            }
+
+            fn run(&self, context: &SystemContext<'_>, state: &mut Self::State) {
+                // Calling through a plain generic function lets the compiler pick the
+                // Fn impl taking the fetched items instead of the declared parameter types.
+                fn call<$($param),*>(func: impl Fn($($param),*), $($param: $param),*) {
+                    func($($param),*)
+                }
+                $(let $param = $param::fetch(context, &mut state.$index);)*
+                call(self, $($param),*)
+            }
+        }
+    };
+}
+
+impl_system_function!();
+impl_system_function!((A, 0));
+impl_system_function!((A, 0), (B, 1));
+impl_system_function!((A, 0), (B, 1), (C, 2));
+impl_system_function!((A, 0), (B, 1), (C, 2), (D, 3));
+impl_system_function!((A, 0), (B, 1), (C, 2), (D, 3), (E, 4));
+impl_system_function!((A, 0), (B, 1), (C, 2), (D, 3), (E, 4), (F, 5));
+impl_system_function!((A, 0), (B, 1), (C, 2), (D, 3), (E, 4), (F, 5), (G, 6));
+impl_system_function!((A, 0), (B, 1), (C, 2), (D, 3), (E, 4), (F, 5), (G, 6), (H, 7));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{register_components, Added, Changed, Entity, With, Without};
+
+    struct Pos(i32);
+    struct Vel(i32);
+    struct Frozen;
+
+    register_components!(Pos, Vel, Frozen);
+
+    fn movement(mut query: Query<(&mut Pos, &Vel), Without<Frozen>>) {
+        for (_, pos, vel) in query.iter() {
+            pos.0 += vel.0;
+        }
+    }
+
+    fn access_of<Params, F: SystemFunction<Params>>(_: &F) -> Access {
+        F::access()
+    }
+
+    #[test]
+    fn function_access() {
+        assert_eq!(access_of(&movement), Access::new().write::<Pos>().read::<Vel>().read::<Frozen>());
+        assert_eq!(access_of(&|_: Commands| {}), Access::new());
+        assert_eq!(access_of(&|| {}), Access::new());
+    }
+
+    #[test]
+    fn function_params() {
+        let manager = Manager::new();
+        for i in 0..3 {
+            let entity = manager.add_entity();
+            manager.add_component(&entity, Pos(0));
+            manager.add_component(&entity, Vel(i));
+        }
+        manager.add_component(&Entity::new(2, 0), Frozen);
+
+        manager.add_system("movement", movement);
+        manager.add_system("freeze", |mut commands: Commands, mut query: Query<(&Pos,)>, mut frozen: Commands| {
+            for (entity, pos) in query.iter() {
+                if pos.0 > 0 {
+                    commands.add_component(&entity, Frozen);
+                }
+            }
+            frozen.remove_component::<Frozen>(&Entity::new(2, 0));
+        }).after("movement");
+        manager.run_schedule().unwrap();
+
+        let mut query = manager.query_filtered::<(&Pos,), With<Frozen>>();
+        let frozen: Vec<u32> = query.iter().map(|(e, _)| e.get_index()).collect();
+        assert_eq!(frozen, vec![1]);
+    }
+
+    struct Seen(Vec<u32>, usize);
+
+    #[test]
+    fn change_detection() {
+        let manager = Manager::new();
+        for i in 0..2 {
+            let entity = manager.add_entity();
+            manager.add_component(&entity, Pos(0));
+            manager.add_component(&entity, Vel(i));
+        }
+        manager.add_component(&Entity::new(1, 0), Frozen);
+        manager.insert_resource(Seen(Vec::new(), 0));
+
+        manager.add_system("movement", movement);
+        manager.add_system("seen", |mut changed: Query<(&Pos,), Changed<Pos>>, mut added: Query<(&Pos,), Added<Pos>>, mut seen: ResMut<Seen>| {
+            seen.0 = changed.iter().map(|(e, _)| e.get_index()).collect();
+            seen.1 = added.iter().count();
+        }).after("movement");
+        let run = || {
+            manager.run_schedule().unwrap();
+            let seen = manager.resource::<Seen>().unwrap();
+            (seen.0.clone(), seen.1)
+        };
+
+        assert_eq!(run(), (vec![0, 1], 2));
+        assert_eq!(run(), (vec![0], 0));
+
+        manager.add_component(&Entity::new(1, 0), Pos(5));
+        let entity = manager.add_entity();
+        manager.add_component(&entity, Pos(0));
+        assert_eq!(run(), (vec![0, 1, 2], 1));
+        assert_eq!(run(), (vec![0], 0));
+    }
+}
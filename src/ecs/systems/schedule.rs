@@ -0,0 +1,351 @@
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use super::System;
+use crate::Manager;
+
+///
+/// Stages of a frame, run in declaration order by [`Manager::run_schedule`].
+/// Systems are registered in [`Stage::Update`] unless configured otherwise.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Stage {
+    PreUpdate,
+    #[default]
+    Update,
+    PostUpdate,
+}
+
+impl Stage {
+    /// Every stage in the order they run in.
+    pub const ALL: [Stage; 3] = [Stage::PreUpdate, Stage::Update, Stage::PostUpdate];
+}
+
+///
+/// Error returned when the systems of a schedule can't be ordered.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ScheduleError {
+    /// A system is ordered against a system that isn't registered.
+    UnknownSystem { system: String, dependency: String },
+    /// A system has to run after a system of a later stage.
+    StageOrder { before: String, after: String },
+    /// The ordering constraints form a cycle. Holds the names along the cycle.
+    Cycle(Vec<String>),
+}
+
+impl std::fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScheduleError::UnknownSystem { system, dependency } => {
+                write!(f, "system {} is ordered against unknown system {}", system, dependency)
+            },
+            ScheduleError::StageOrder { before, after } => {
+                write!(f, "system {} has to run before {}, but is in a later stage", before, after)
+            },
+            ScheduleError::Cycle(names) => {
+                write!(f, "systems form a cycle: {} -> {}", names.join(" -> "), names[0])
+            },
+        }
+    }
+}
+
+impl std::error::Error for ScheduleError {}
+
+struct Entry {
+    name: String,
+    // Shared so the systems can run without holding the schedule lock.
+    system: Arc<System>,
+    stage: Stage,
+    before: Vec<String>,
+    after: Vec<String>,
+}
+
+///
+/// The systems registered in a manager, kept in registration order.
+///
+#[derive(Default)]
+pub(crate) struct Schedule {
+    entries: Vec<Entry>,
+}
+
+///
+/// Systems of one stage in the order they run in. `after[i]` holds the positions
+/// of the systems that have to finish before the system at position `i`.
+///
+pub(crate) struct StageOrder {
+    pub systems: Vec<Arc<System>>,
+    pub after: Vec<Vec<usize>>,
+}
+
+impl Schedule {
+    ///
+    /// Inserts a system, replacing the system and constraints registered under the same name.
+    ///
+    pub fn insert(&mut self, name: &str, system: System) {
+        let entry = Entry {
+            name: String::from(name),
+            system: Arc::new(system),
+            stage: Stage::default(),
+            before: Vec::new(),
+            after: Vec::new(),
+        };
+        match self.entries.iter_mut().find(|entry| entry.name == name) {
+            Some(old) => *old = entry,
+            None => self.entries.push(entry),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<System>> {
+        self.entries.iter().find(|entry| entry.name == name).map(|entry| Arc::clone(&entry.system))
+    }
+
+    fn get_mut(&mut self, name: &str) -> Option<&mut Entry> {
+        self.entries.iter_mut().find(|entry| entry.name == name)
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.name == name)
+    }
+
+    ///
+    /// Sorts the systems of every stage so each system runs after its dependencies.
+    /// Systems without constraints between them keep their registration order.
+    ///
+    pub fn order(&self) -> Result<Vec<StageOrder>, ScheduleError> {
+        // preds[i] are the systems of the same stage that have to run before system i.
+        let mut preds: Vec<Vec<usize>> = vec![Vec::new(); self.entries.len()];
+        for (index, entry) in self.entries.iter().enumerate() {
+            for (names, entry_first) in [(&entry.after, false), (&entry.before, true)] {
+                for name in names {
+                    let other = self.position(name).ok_or_else(|| ScheduleError::UnknownSystem {
+                        system: entry.name.clone(),
+                        dependency: name.clone(),
+                    })?;
+                    let (first, then) = if entry_first { (index, other) } else { (other, index) };
+                    let (first_stage, then_stage) = (self.entries[first].stage, self.entries[then].stage);
+                    if first_stage == then_stage {
+                        preds[then].push(first);
+                    } else if first_stage > then_stage {
+                        return Err(ScheduleError::StageOrder {
+                            before: self.entries[first].name.clone(),
+                            after: self.entries[then].name.clone(),
+                        });
+                    }
+                }
+            }
+        }
+
+        let mut stages = Vec::new();
+        for stage in Stage::ALL {
+            let members: Vec<usize> = (0..self.entries.len()).filter(|&i| self.entries[i].stage == stage).collect();
+            let sorted = self.sort(&members, &preds)?;
+
+            let mut after = Vec::with_capacity(sorted.len());
+            for &index in &sorted {
+                let mut positions: Vec<usize> = preds[index].iter()
+                    .map(|pred| sorted.iter().position(|i| i == pred).unwrap())
+                    .collect();
+                positions.sort_unstable();
+                positions.dedup();
+                after.push(positions);
+            }
+            stages.push(StageOrder {
+                systems: sorted.iter().map(|&index| Arc::clone(&self.entries[index].system)).collect(),
+                after,
+            });
+        }
+        Ok(stages)
+    }
+
+    /// Topologically sorts the members, always picking the earliest registered system that is ready.
+    fn sort(&self, members: &[usize], preds: &[Vec<usize>]) -> Result<Vec<usize>, ScheduleError> {
+        let mut waiting: Vec<usize> = vec![0; self.entries.len()];
+        for &index in members {
+            waiting[index] = preds[index].len();
+        }
+        let mut ready: BTreeSet<usize> = members.iter().copied().filter(|&i| waiting[i] == 0).collect();
+        let mut sorted = Vec::with_capacity(members.len());
+
+        while let Some(index) = ready.pop_first() {
+            sorted.push(index);
+            for &other in members {
+                let count = preds[other].iter().filter(|&&pred| pred == index).count();
+                if count > 0 {
+                    waiting[other] -= count;
+                    if waiting[other] == 0 {
+                        ready.insert(other);
+                    }
+                }
+            }
+        }
+
+        if sorted.len() < members.len() {
+            return Err(ScheduleError::Cycle(self.find_cycle(members, &sorted, preds)));
+        }
+        Ok(sorted)
+    }
+
+    ///
+    /// Finds a cycle among the unsorted members. Every one of them waits on another
+    /// unsorted member, so following those back always ends in a cycle.
+    ///
+    fn find_cycle(&self, members: &[usize], sorted: &[usize], preds: &[Vec<usize>]) -> Vec<String> {
+        let unsorted = |index: &usize| !sorted.contains(index);
+        let mut path: Vec<usize> = vec![*members.iter().find(|i| unsorted(i)).unwrap()];
+        loop {
+            let current = *path.last().unwrap();
+            let pred = *preds[current].iter().find(|i| unsorted(i)).unwrap();
+            if let Some(start) = path.iter().position(|&i| i == pred) {
+                let mut cycle: Vec<usize> = path[start..].iter().rev().copied().collect();
+                // Start at the earliest registered system so the error doesn't depend on where the walk began.
+                let first = cycle.iter().enumerate().min_by_key(|(_, &i)| i).unwrap().0;
+                cycle.rotate_left(first);
+                return cycle.iter().map(|&i| self.entries[i].name.clone()).collect();
+            }
+            path.push(pred);
+        }
+    }
+}
+
+///
+/// Handle returned when registering a system, used to place it in the schedule.
+///
+/// # Examples
+///
+/// ```
+/// use rust_ecs::*;
+///
+/// let manager = Manager::new();
+///
+/// manager.register_task("physics", |_, _| {});
+/// manager.register_task("input", |_, _| {}).in_stage(Stage::PreUpdate);
+/// manager.register_task("ai", |_, _| {}).after("input").before("physics");
+///
+/// manager.run_schedule().unwrap();
+/// ```
+pub struct SystemConfig<'m> {
+    manager: &'m Manager,
+    name: String,
+}
+
+impl<'m> SystemConfig<'m> {
+    pub(crate) fn new(manager: &'m Manager, name: &str) -> Self {
+        SystemConfig {
+            manager,
+            name: String::from(name),
+        }
+    }
+
+    /// Moves the system to the given stage.
+    pub fn in_stage(self, stage: Stage) -> Self {
+        self.configure(|entry| entry.stage = stage);
+        self
+    }
+
+    /// Makes the system run before the system with the given name.
+    pub fn before(self, name: &str) -> Self {
+        self.configure(|entry| entry.before.push(String::from(name)));
+        self
+    }
+
+    /// Makes the system run after the system with the given name.
+    pub fn after(self, name: &str) -> Self {
+        self.configure(|entry| entry.after.push(String::from(name)));
+        self
+    }
+
+    fn configure<F: FnOnce(&mut Entry)>(&self, func: F) {
+        if let Some(entry) = self.manager.schedule.write().unwrap().get_mut(&self.name) {
+            func(entry);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(schedule: &Schedule, stage: &StageOrder) -> Vec<String> {
+        stage.systems.iter()
+            .map(|system| {
+                let entry = schedule.entries.iter().find(|entry| Arc::ptr_eq(&entry.system, system)).unwrap();
+                entry.name.clone()
+            })
+            .collect()
+    }
+
+    fn schedule(systems: &[(&str, Stage, &[&str], &[&str])]) -> Schedule {
+        let mut schedule = Schedule::default();
+        for (name, stage, before, after) in systems {
+            schedule.insert(name, System::new(|_, _| {}));
+            let entry = schedule.get_mut(name).unwrap();
+            entry.stage = *stage;
+            entry.before = before.iter().map(|name| String::from(*name)).collect();
+            entry.after = after.iter().map(|name| String::from(*name)).collect();
+        }
+        schedule
+    }
+
+    #[test]
+    fn order_registration() {
+        let schedule = schedule(&[
+            ("a", Stage::Update, &[], &[]),
+            ("b", Stage::PreUpdate, &[], &[]),
+            ("c", Stage::Update, &[], &[]),
+            ("d", Stage::PostUpdate, &[], &[]),
+        ]);
+        let stages = schedule.order().unwrap();
+
+        assert_eq!(names(&schedule, &stages[0]), vec!["b"]);
+        assert_eq!(names(&schedule, &stages[1]), vec!["a", "c"]);
+        assert_eq!(names(&schedule, &stages[2]), vec!["d"]);
+    }
+
+    #[test]
+    fn order_constraints() {
+        let schedule = schedule(&[
+            ("a", Stage::Update, &[], &["c"]),
+            ("b", Stage::Update, &["c"], &[]),
+            ("c", Stage::Update, &[], &[]),
+            ("d", Stage::Update, &[], &["e"]),
+            ("e", Stage::PreUpdate, &[], &[]),
+        ]);
+        let stages = schedule.order().unwrap();
+
+        assert_eq!(names(&schedule, &stages[1]), vec!["b", "c", "a", "d"]);
+        assert_eq!(stages[1].after, vec![vec![], vec![0], vec![1], vec![]]);
+    }
+
+    #[test]
+    fn order_cycle() {
+        let schedule = schedule(&[
+            ("a", Stage::Update, &[], &[]),
+            ("b", Stage::Update, &[], &["d"]),
+            ("c", Stage::Update, &[], &["b"]),
+            ("d", Stage::Update, &[], &["c"]),
+        ]);
+        let error = schedule.order().err().unwrap();
+
+        assert_eq!(error, ScheduleError::Cycle(vec![String::from("b"), String::from("c"), String::from("d")]));
+        assert_eq!(error.to_string(), "systems form a cycle: b -> c -> d -> b");
+    }
+
+    #[test]
+    fn order_errors() {
+        let unknown = schedule(&[("a", Stage::Update, &["x"], &[])]);
+        assert_eq!(unknown.order().err(), Some(ScheduleError::UnknownSystem {
+            system: String::from("a"),
+            dependency: String::from("x"),
+        }));
+
+        let stages = schedule(&[
+            ("a", Stage::PreUpdate, &[], &["b"]),
+            ("b", Stage::Update, &[], &[]),
+        ]);
+        assert_eq!(stages.order().err(), Some(ScheduleError::StageOrder {
+            before: String::from("b"),
+            after: String::from("a"),
+        }));
+    }
+}
@@ -0,0 +1,140 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::System;
+use crate::{Commands, Manager};
+
+///
+/// Splits the systems into batches of systems that don't conflict with each other.
+/// Every system is put in the first batch after the last one holding a conflicting system
+/// or one of the systems in `after[index]`, so conflicting systems keep the order they were given in.
+/// `after` may only point to earlier systems, or be empty if there are no extra constraints.
+///
+pub(crate) fn batches(systems: &[&System], after: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    let mut batches: Vec<Vec<usize>> = Vec::new();
+    for (index, system) in systems.iter().enumerate() {
+        let must_wait = |other: usize| {
+            system.access().conflicts(systems[other].access())
+                || after.get(index).is_some_and(|after| after.contains(&other))
+        };
+        let first_free = batches.iter()
+            .rposition(|batch| batch.iter().any(|&other| must_wait(other)))
+            .map_or(0, |conflict| conflict + 1);
+
+        match batches.get_mut(first_free) {
+            Some(batch) => batch.push(index),
+            None => batches.push(vec![index]),
+        }
+    }
+    batches
+}
+
+///
+/// Runs the systems batch by batch. The systems of a batch are shared between a pool of
+/// worker threads, and their commands are applied in order once the whole batch is done.
+/// The workers are scoped threads spawned for each batch, since the systems borrow the manager
+/// for the call only. Batches of a single system run on the calling thread.
+///
+/// # Panics
+///
+/// Panics if a system panics, or if the comp_manager is borrowed mutably.
+pub(crate) fn run_parallel(manager: &Manager, systems: &[&System], after: &[Vec<usize>]) {
+    let workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+
+    for batch in batches(systems, after) {
+        let next = AtomicUsize::new(0);
+        let run_next = || {
+            let mut done = Vec::new();
+            loop {
+                let position = next.fetch_add(1, Ordering::Relaxed);
+                let Some(&index) = batch.get(position) else {
+                    return done;
+                };
+                let mut commands = Commands::new(manager);
+                systems[index].run(manager.get_comp_manager(), &mut commands);
+                done.push((position, commands));
+            }
+        };
+
+        let mut done = if batch.len() == 1 || workers == 1 {
+            run_next()
+        } else {
+            std::thread::scope(|scope| {
+                let handles: Vec<_> = (0..workers.min(batch.len()))
+                    .map(|_| scope.spawn(run_next))
+                    .collect();
+                handles.into_iter()
+                    .flat_map(|handle| handle.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+                    .collect()
+            })
+        };
+
+        done.sort_by_key(|(position, _)| *position);
+        for (_, commands) in done {
+            commands.apply();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::systems::Access;
+    use crate::register_components;
+
+    struct Pos;
+    struct Vel;
+    struct Hp;
+
+    register_components!(Pos, Vel, Hp);
+
+    fn system(access: Access) -> System {
+        System::with_access(access, |_, _| {})
+    }
+
+    #[test]
+    fn batch_independent() {
+        let a = system(Access::new().write::<Pos>().read::<Vel>());
+        let b = system(Access::new().write::<Hp>());
+        let c = system(Access::new().read::<Vel>());
+
+        assert_eq!(batches(&[&a, &b, &c], &[]), vec![vec![0, 1, 2]]);
+    }
+
+    #[test]
+    fn batch_conflicts() {
+        let a = system(Access::new().write::<Pos>());
+        let b = system(Access::new().read::<Pos>());
+        let c = system(Access::new().write::<Hp>());
+        let d = system(Access::new().read::<Pos>().write::<Hp>());
+
+        assert_eq!(batches(&[&a, &b, &c, &d], &[]), vec![vec![0, 2], vec![1, 3]]);
+    }
+
+    #[test]
+    fn batch_keeps_order() {
+        let a = system(Access::new().write::<Pos>());
+        let b = system(Access::new().write::<Vel>());
+        let c = system(Access::new().write::<Pos>().write::<Vel>());
+        let d = system(Access::new().read::<Vel>());
+
+        assert_eq!(batches(&[&a, &b, &c, &d], &[]), vec![vec![0, 1], vec![2], vec![3]]);
+    }
+
+    #[test]
+    fn batch_exclusive() {
+        let a = system(Access::new().read::<Pos>());
+        let b = System::new(|_, _| {});
+        let c = system(Access::new().read::<Vel>());
+
+        assert_eq!(batches(&[&a, &b, &c], &[]), vec![vec![0], vec![1], vec![2]]);
+    }
+
+    #[test]
+    fn batch_after() {
+        let a = system(Access::new().read::<Pos>());
+        let b = system(Access::new().read::<Vel>());
+        let c = system(Access::new().read::<Hp>());
+
+        assert_eq!(batches(&[&a, &b, &c], &[vec![], vec![0], vec![]]), vec![vec![0, 2], vec![1]]);
+    }
+}
@@ -0,0 +1,208 @@
+use std::any::TypeId;
+use std::collections::{HashMap, HashSet};
+
+use crate::ecs::cm::GroupFn;
+use crate::{Children, Component, ComponentManager, Entity, Manager, Parent};
+
+pub(crate) fn clone_component<T: Component + Clone>(cm: &mut ComponentManager, source: &Entity, target: &Entity) -> Option<GroupFn> {
+    let component = cm.get_components::<T>()?.get(source)?.clone();
+    cm.insert_component(target, component);
+    Some(T::group)
+}
+
+impl Manager {
+    ///
+    /// Spawns a copy of the entity with a clone of each of its components registered with
+    /// [`ComponentManager::register_cloneable`], grouped like the original. Other components are
+    /// left out. The clone gets the same parent, but no children. Relations are not copied.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the entity is dead or if the comp_manager is borrowed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    /// #[derive(Clone)]
+    /// struct Damage(u32);
+    ///
+    /// register_components!(Damage);
+    ///
+    /// let manager = Manager::new();
+    /// manager.get_comp_manager_mut().register_cloneable::<Damage>();
+    ///
+    /// let template = manager.spawn().with(Damage(5)).id();
+    /// let projectile = manager.clone_entity(&template);
+    /// assert_ne!(template, projectile);
+    /// assert_eq!(5, manager.get_comp_manager().get_components::<Damage>().unwrap().get(&projectile).unwrap().0);
+    /// ```
+    pub fn clone_entity(&self, entity: &Entity) -> Entity {
+        let mut comp_manager = self.get_comp_manager_mut();
+        assert!(self.entity_alive(*entity), "Can't clone the dead entity {:?}", entity);
+        let clone = self.add_entity();
+
+        let hierarchy = [TypeId::of::<Parent>(), TypeId::of::<Children>()];
+        for group in comp_manager.clone_components(entity, &clone, &hierarchy) {
+            group(&comp_manager, &clone);
+        }
+        if let Some(parent) = comp_manager.parent_of(entity) {
+            comp_manager.set_parent(&clone, &parent);
+        }
+        clone
+    }
+
+    ///
+    /// Moves the entities and all of their components from `source` into this manager and returns
+    /// the new entity of each moved one. Dead and repeated entities are skipped. The entities are
+    /// killed in `source`, the moved entities are grouped in this manager once all of their
+    /// components are in. Parents and children are kept among the moved entities, links to
+    /// entities that are not moved are cut. Relations are not moved, the source removes them
+    /// like for any killed entity. Remove hooks fire in `source` and add hooks in this manager.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `source` is this manager or if either comp_manager is borrowed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_ecs::*;
+    /// struct Pos(i32);
+    ///
+    /// register_components!(Pos);
+    ///
+    /// let loading = Manager::new();
+    /// let live = Manager::new();
+    /// let entity = loading.spawn().with(Pos(3)).id();
+    ///
+    /// let mapping = live.move_entities_from(&loading, &[entity]);
+    /// assert!(!loading.entity_alive(entity));
+    /// assert_eq!(3, live.get_comp_manager().get_components::<Pos>().unwrap().get(&mapping[&entity]).unwrap().0);
+    /// ```
+    pub fn move_entities_from(&self, source: &Manager, entities: &[Entity]) -> HashMap<Entity, Entity> {
+        assert!(!std::ptr::eq(self, source), "Can't move entities into the manager they are in");
+        let mut comp_manager = self.get_comp_manager_mut();
+        let source_cm = source.get_comp_manager_mut();
+
+        let mut moved = Vec::with_capacity(entities.len());
+        let mut inside = HashSet::with_capacity(entities.len());
+        for entity in entities {
+            if source.entity_alive(*entity) && inside.insert(*entity) {
+                moved.push(*entity);
+            }
+        }
+
+        for entity in moved.iter() {
+            if source_cm.parent_of(entity).is_some_and(|parent| !inside.contains(&parent)) {
+                source_cm.remove_parent(entity);
+            }
+            for child in source_cm.children_of(entity) {
+                if !inside.contains(&child) {
+                    source_cm.remove_parent(&child);
+                }
+            }
+        }
+
+        let mapping: HashMap<Entity, Entity> = {
+            let mut ent_handler = self.ent_handler.lock().unwrap();
+            ent_handler.reserve(moved.len());
+            moved.iter().map(|entity| (*entity, ent_handler.new_entity())).collect()
+        };
+        for entity in moved.iter() {
+            let target = mapping[entity];
+            for group in source_cm.move_components(&mut comp_manager, entity, &target) {
+                group(&comp_manager, &target);
+            }
+        }
+        comp_manager.remap_hierarchy(&mapping);
+
+        source.kill_entities(&source_cm, moved);
+        mapping
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone)]
+    struct Pos(i32);
+    #[derive(Clone)]
+    struct Vel(i32);
+    struct Sprite;
+
+    group!(Pos, Vel);
+    register_components!(Sprite);
+
+    #[test]
+    fn clone() {
+        let manager = Manager::new();
+        {
+            let mut cm = manager.get_comp_manager_mut();
+            cm.register_cloneable::<Pos>();
+            cm.register_cloneable::<Vel>();
+            cm.register_cloneable::<Parent>();
+            cm.register_cloneable::<Children>();
+        }
+        let parent = manager.add_entity();
+        let template = manager.spawn().with(Pos(1)).with(Vel(2)).with(Sprite).id();
+        let child = manager.add_entity();
+        manager.set_parent(&template, &parent);
+        manager.set_parent(&child, &template);
+
+        let clone = manager.clone_entity(&template);
+        let cm = manager.get_comp_manager();
+        assert_eq!(cm.group_len::<Pos>(), 2);
+        assert_eq!(cm.get_components::<Pos>().unwrap().get(&clone).unwrap().0, 1);
+        assert_eq!(cm.get_components::<Vel>().unwrap().get(&clone).unwrap().0, 2);
+        assert!(!cm.has_component::<Sprite>(&clone));
+        assert_eq!(cm.parent_of(&clone), Some(parent));
+        assert_eq!(cm.children_of(&parent), vec![template, clone]);
+        assert!(cm.children_of(&clone).is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn clone_dead() {
+        let manager = Manager::new();
+        let entity = manager.add_entity();
+        manager.kill_entity(entity);
+        manager.clone_entity(&entity);
+    }
+
+    #[test]
+    fn move_between_managers() {
+        let source = Manager::new();
+        let target = Manager::new();
+        target.add_entity();
+
+        let outside = source.add_entity();
+        let root = source.spawn().with(Pos(1)).with(Vel(1)).id();
+        let child = source.spawn().with(Pos(2)).with(Sprite).id();
+        let stray = source.add_entity();
+        let dead = source.add_entity();
+        source.kill_entity(dead);
+        source.set_parent(&root, &outside);
+        source.set_parent(&child, &root);
+        source.set_parent(&stray, &child);
+
+        let mapping = target.move_entities_from(&source, &[root, child, root, dead]);
+        assert_eq!(mapping.len(), 2);
+        assert!(!source.entity_alive(root) && !source.entity_alive(child));
+        assert!(source.children_of(&outside).is_empty() && source.parent_of(&stray).is_none());
+        assert!(source.get_comp_manager().get_components::<Pos>().unwrap().is_empty());
+
+        let (root, child) = (mapping[&root], mapping[&child]);
+        assert!(target.entity_alive(root) && target.entity_alive(child));
+        let cm = target.get_comp_manager();
+        assert_eq!(cm.group_len::<Pos>(), 1);
+        assert_eq!(cm.get_components::<Vel>().unwrap().get(&root).unwrap().0, 1);
+        assert_eq!(cm.get_components::<Pos>().unwrap().get(&child).unwrap().0, 2);
+        assert!(cm.has_component::<Sprite>(&child));
+        assert_eq!(cm.parent_of(&root), None);
+        assert_eq!(cm.parent_of(&child), Some(root));
+        assert_eq!(cm.children_of(&root), vec![child]);
+        assert!(cm.children_of(&child).is_empty());
+    }
+}